@@ -1,10 +1,13 @@
 use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap::parser::ValueSource;
 use site_tester::*;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool};
+use std::thread;
+use std::time::{Duration, Instant};
 use atty::Stream;
 use std::io;
 use std::process::exit;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 const RED: &str     = "\x1b[31m";
 const GREEN: &str   = "\x1b[32m";
@@ -16,99 +19,353 @@ const RESET: &str   = "\x1b[0m";
 const BOLD: &str    = "\x1b[1m";
 const ORANGE: &str  = "\x1b[38;5;202m";
 
+/// Cleared when stdout is not a terminal or --quiet is set
+static COLOUR: AtomicBool = AtomicBool::new(true);
+
+/// `println!` that strips the colour codes when colour is off
+macro_rules! say {
+    ($($arg:tt)*) => {
+        emit(format!($($arg)*))
+    };
+}
+
 pub struct Config {
-    pub url: String,
-    pub follow_links: bool,
-    pub number: u32,
-    pub processes: u32,
-    pub method: Method,
-    pub payload: String,
-    pub ignore_ssl: bool,
-    pub timeout: u16, // store as milliseconds
-    pub verbose: bool,
+    pub test: LoadTestConfig,
     pub skip_confirm: bool,
+    /// Print every request, not just failures
+    pub verbose: bool,
+    /// Only print the final report
+    pub quiet: bool,
+    pub outputs: Vec<ReportOutput>,
+    /// CSV file for one row per request
+    pub raw_csv: Option<String>,
 }
 
 impl Config {
-    pub fn from_matches(matches: &clap::ArgMatches) -> Self {
-        let timeout_secs = *matches.get_one::<f32>("timeout").unwrap();
-        let timeout_ms = (timeout_secs * 1000.0) as u16;
-        let url = matches.get_one::<String>("url").unwrap().to_string();
-        let url = normalise_url(url, matches.get_flag("force-url"));
-        let method_str = matches.get_one::<String>("type").unwrap();
-        Self {
-            url,
-            follow_links: matches.get_flag("follow-links"),
-            number: *matches.get_one::<u32>("number").unwrap(),
-            processes: *matches.get_one::<u32>("processes").unwrap(),
-            method: Method::from_str(method_str),
-            payload: matches.get_one::<String>("payload").unwrap().to_string(),
-            ignore_ssl: matches.get_flag("ignore-ssl"),
-            timeout: timeout_ms,
-            verbose: matches.get_flag("verbose"),
+    pub fn from_matches(matches: &clap::ArgMatches) -> Result<Self, String> {
+        let (mut test, mut outputs) = match matches.get_one::<String>("scenario") {
+            Some(path) => {
+                let (scenario, config) = Scenario::load(path)?;
+                (config, scenario.report_outputs()?)
+            }
+            None => (Self::test_from_flags(matches)?, Vec::new()),
+        };
+        for threshold in matches.get_many::<Threshold>("threshold").unwrap_or_default() {
+            test = test.threshold(threshold.clone());
+        }
+        outputs.extend(matches.get_many::<ReportOutput>("output").unwrap_or_default().cloned());
+        Ok(Self {
+            test,
             skip_confirm: matches.get_flag("skip-confirm"),
+            verbose: matches.get_flag("verbose"),
+            quiet: matches.get_flag("quiet"),
+            outputs,
+            raw_csv: matches.get_one::<String>("raw-csv").cloned(),
+        })
+    }
+
+    fn test_from_flags(matches: &clap::ArgMatches) -> Result<LoadTestConfig, String> {
+        let timeout = parse_timeout(*matches.get_one::<f64>("timeout").unwrap())?;
+        let endpoints = match matches.get_one::<String>("url-file") {
+            Some(path) => Endpoint::read_file(path)?,
+            None => matches.get_many::<Endpoint>("url").unwrap().cloned().collect(),
+        };
+        let endpoints: Vec<Endpoint> = endpoints
+            .into_iter()
+            .map(|endpoint| Endpoint::new(normalise_url(endpoint.url, matches.get_flag("force-url")), endpoint.weight))
+            .collect();
+        let method = matches.get_one::<Method>("type").unwrap().clone();
+        let payload = resolve_payload(matches.get_one::<String>("payload").unwrap())?;
+        let headers = build_headers(
+            matches.get_many::<String>("header").unwrap_or_default().map(String::as_str),
+            matches.get_one::<String>("bearer").map(String::as_str),
+            matches.get_one::<String>("basic-auth").map(String::as_str),
+        )?;
+        let rate = match matches.get_one::<String>("rate") {
+            Some(rate) => Some(Rate::parse(rate, *matches.get_one::<Arrival>("arrival").unwrap())?),
+            None => None,
+        };
+        let stages = matches.get_one::<Stages>("stages").cloned();
+
+        let mut test = LoadTestConfig::new(endpoints[0].url.clone())
+            .method(method)
+            .headers(headers)
+            .expected_status(matches.get_one::<ExpectedStatus>("expect-status").unwrap().clone())
+            .timeout(timeout)
+            .ignore_ssl(matches.get_flag("ignore-ssl"));
+        test = match matches.get_one::<Duration>("duration") {
+            Some(duration) => test.duration(*duration),
+            None => test.requests(*matches.get_one::<u64>("number").unwrap()),
+        };
+        if let Some(rate) = rate {
+            test = test.rate(rate);
+        }
+        if let Some(stages) = stages {
+            test = test.stages(stages);
+        }
+        if matches.value_source("processes") != Some(ValueSource::DefaultValue) {
+            test = test.workers(*matches.get_one::<u32>("processes").unwrap());
+        }
+        if let Some(payload) = payload {
+            test = test.payload(payload);
+        }
+        if matches.get_flag("follow-links") {
+            test = test.follow_links(CrawlOptions {
+                max_depth: *matches.get_one::<u32>("max-depth").unwrap(),
+                max_pages: *matches.get_one::<usize>("max-pages").unwrap(),
+            });
+        }
+        if endpoints.len() > 1 {
+            for endpoint in endpoints {
+                test = test.endpoint(endpoint);
+            }
         }
+        if let Some(path) = matches.get_one::<String>("csv") {
+            let strategy = *matches.get_one::<feeder::Strategy>("csv-strategy").unwrap();
+            test = test.feeder(Feeder::from_csv("csv", path, strategy)?);
+        }
+        Ok(test)
     }
 }
 
 pub fn run_cli() {
     
     let matches = get_arguments();
+    let tolerance = *matches.get_one::<f64>("tolerance").unwrap();
+    if let Some(compare) = matches.subcommand_matches("compare") {
+        run_compare(compare, tolerance);
+        return;
+    }
 
-    let config = Config::from_matches(&matches);
-
-    menu(&config);
-
-    let url = Arc::new(config.url.clone());
-
-    let client = get_client(config.timeout, config.ignore_ssl);
+    let config = match Config::from_matches(&matches) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(e);
+            exit(1);
+        }
+    };
+    // A report on stdout must not be mixed up with anything else
+    let report_on_stdout = config.outputs.iter().any(ReportOutput::to_stdout);
+    let quiet = config.quiet || report_on_stdout;
+    COLOUR.store(!quiet && atty::is(Stream::Stdout), Ordering::Relaxed);
 
-    let times = Arc::new(Mutex::new(vec![0; config.number as usize]));
-    let cancel_flag = Arc::new(AtomicBool::new(false));
+    if !quiet {
+        menu(&config);
+    }
 
-    let ok_closure = |msg: String| print_message(msg);
-    let err_closure = |msg: String| print_error(msg);
+    let baseline = match matches.get_one::<String>("baseline").map(RunSummary::load).transpose() {
+        Ok(baseline) => baseline,
+        Err(e) => {
+            print_error(e);
+            exit(1);
+        }
+    };
 
-    make_requests(
-        url,
-        config.number,
-        config.processes,
-        Arc::clone(&client),
-        config.verbose,
-        (ok_closure, err_closure),
-        Arc::clone(&times),
-        Arc::clone(&cancel_flag),
-        config.method
-    );
+    let verbose = config.verbose;
+    let mut test_config = config.test;
+    if !quiet {
+        test_config = test_config.subscribe(move |event: &Event| print_event(event, verbose));
+    }
+    let samples = match config.raw_csv.as_deref().map(SampleWriter::create).transpose() {
+        Ok(samples) => samples.map(Arc::new),
+        Err(e) => {
+            print_error(e);
+            exit(1);
+        }
+    };
+    if let Some(samples) = &samples {
+        let samples = Arc::clone(samples);
+        test_config = test_config.subscribe(move |event: &Event| samples.on_event(event));
+    }
+    let mut test = match LoadTest::start(test_config) {
+        Ok(test) => test,
+        Err(e) => {
+            print_error(e);
+            exit(1);
+        }
+    };
 
-    let mut last_print: std::time::Instant = std::time::Instant::now();
+    let mut last_print: Instant = Instant::now();
     loop {
-        let completed = {
-            let t = times.lock().unwrap();
-            t.iter().filter(|&&x| x != 0).count()
-        };
-        if completed >= config.number as usize {
+        let progress = test.progress();
+        if progress.finished {
             break;
         }
-        if !config.verbose {
-            if last_print.elapsed().as_millis() >= 500 {
-                println!("{CYAN}{}/{}{RESET}", completed, config.number);
-                last_print = std::time::Instant::now();
+        if !verbose && !quiet && last_print.elapsed().as_millis() >= 500 {
+            match progress.length {
+                RunLength::Requests(number) => say!("{CYAN}{}/{}{RESET}", progress.completed, number),
+                RunLength::Duration(duration) => say!(
+                    "{CYAN}{}/{} - {} requests{RESET}",
+                    format_duration(Duration::from_secs(progress.elapsed.as_secs())),
+                    format_duration(duration),
+                    progress.completed
+                ),
             }
+            last_print = Instant::now();
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let report = test.wait();
+    if let Some(Err(e)) = samples.map(|samples| samples.finish()) {
+        print_error(e);
+        exit(1);
+    }
+    if !report_on_stdout {
+        print_report(&report);
+    }
+    for output in &config.outputs {
+        if let Err(e) = output.write(&report) {
+            print_error(e);
+            exit(1);
+        }
+    }
+    let thresholds = report.check_thresholds();
+    if !report_on_stdout && !thresholds.is_empty() {
+        say!("{GREEN}Thresholds:{RESET}");
+        for result in &thresholds {
+            let colour = if result.passed { GREEN } else { RED };
+            say!("  {colour}{result}{RESET}");
+        }
+    }
+    let mut regressed = false;
+    if let Some(baseline) = baseline {
+        let comparison = Comparison::new(&baseline, &RunSummary::from_report(&report), tolerance);
+        if !report_on_stdout {
+            say!("{GREEN}Compared with the baseline:{RESET}");
+            print_comparison(&comparison);
+        }
+        regressed = comparison.has_regression();
+    }
+    if regressed || thresholds.iter().any(|result| !result.passed) {
+        exit(1);
+    }
+}
+
+/// Compares two saved JSON reports and exits with an error on a regression
+fn run_compare(matches: &ArgMatches, tolerance: f64) {
+    COLOUR.store(atty::is(Stream::Stdout), Ordering::Relaxed);
+    let load = |name: &str| match RunSummary::load(matches.get_one::<String>(name).unwrap()) {
+        Ok(summary) => summary,
+        Err(e) => {
+            print_error(e);
+            exit(1);
         }
-        std::thread::sleep(std::time::Duration::from_millis(50));
-    }
-
-    let times_vec = times.lock().unwrap();
-    let (average_value, fails, max_time) = get_average(&times_vec);
-    println!(
-        "{GREEN}Completed a total of {BLUE}{number_requests}{GREEN} requests, \
-        with an average time of {BLUE}{:?}{GREEN} for successful requests and \
-        {BLUE}{fails}{GREEN} failed requests and a max request time of {BLUE}{:?}{GREEN}",
-        average_value,
-        max_time,
-        number_requests = config.number
+    };
+    let comparison = Comparison::new(&load("old"), &load("new"), tolerance);
+    print_comparison(&comparison);
+    if comparison.has_regression() {
+        exit(1);
+    }
+}
+
+fn print_comparison(comparison: &Comparison) {
+    for change in &comparison.changes {
+        let colour = if change.regression { RED } else { BLUE };
+        say!("  {colour}{change}{RESET}");
+    }
+    match comparison.significance_line() {
+        Some(line) => say!("  {CYAN}{line}{RESET}"),
+        None => say!("  {ORANGE}No raw samples to test the latency difference for significance{RESET}"),
+    }
+    if comparison.has_regression() {
+        say!("{RED}Performance regressed{RESET}");
+    } else {
+        say!("{GREEN}No regressions{RESET}");
+    }
+}
+
+fn print_report(report: &Report) {
+    let stats = &report.stats;
+    say!(
+        "{GREEN}Completed a total of {BLUE}{number_requests}{GREEN} requests{RESET}",
+        number_requests = stats.total()
     );
+    for (label, value) in stats.summary() {
+        say!("  {GREEN}{label}: {BLUE}{value}{RESET}");
+    }
+    if let Some(warning) = report.rate_warning() {
+        say!("{ORANGE}{warning}{RESET}");
+    }
+    if !stats.status_codes().is_empty() {
+        say!("{GREEN}Status codes:{RESET}");
+        for line in stats.status_lines() {
+            say!("  {BLUE}{line}{RESET}");
+        }
+    }
+    if stats.successes() > 0 {
+        say!("{GREEN}Latency histogram:{RESET}");
+        for line in stats.histogram_lines(10, 40) {
+            say!("  {CYAN}{line}{RESET}");
+        }
+    }
+
+    let per_stage = report.per_stage();
+    if !per_stage.is_empty() {
+        let unit = if report.config.profile.rate.is_some() { " requests/s" } else { " workers" };
+        say!("{GREEN}Stages:{RESET}");
+        for (i, (stage, stage_stats)) in per_stage.into_iter().enumerate() {
+            say!(
+                "  {BLUE}Stage {} ({} to {}{unit}){RESET}: {} requests, {:.1} requests/s, average {:?}, p95 {:?}, {} errors",
+                i + 1,
+                format_duration(stage.duration),
+                stage.target,
+                stage_stats.total(),
+                stage_stats.total() as f64 / stage.duration.as_secs_f64(),
+                stage_stats.mean(),
+                stage_stats.percentile(95.0),
+                stage_stats.fails()
+            );
+        }
+    }
+
+    let per_endpoint = report.per_endpoint();
+    if !per_endpoint.is_empty() {
+        say!("{GREEN}Endpoints:{RESET}");
+        for (endpoint, endpoint_stats) in per_endpoint {
+            say!(
+                "  {BLUE}{url}{RESET} (weight {weight}): {} requests ({:.1}%), average {:?}, p95 {:?}, max {:?}, {} errors",
+                endpoint_stats.total(),
+                100.0 * endpoint_stats.total() as f64 / stats.total().max(1) as f64,
+                endpoint_stats.mean(),
+                endpoint_stats.percentile(95.0),
+                endpoint_stats.max(),
+                endpoint_stats.fails(),
+                url = endpoint.url,
+                weight = endpoint.weight
+            );
+        }
+    }
+
+    let per_step = report.per_step();
+    if !per_step.is_empty() {
+        say!("{GREEN}Steps:{RESET}");
+        for (name, step_stats) in per_step {
+            say!(
+                "  {BLUE}{name}{RESET}: {} requests, average {:?}, p95 {:?}, max {:?}, {} errors",
+                step_stats.total(),
+                step_stats.mean(),
+                step_stats.percentile(95.0),
+                step_stats.max(),
+                step_stats.fails()
+            );
+        }
+    }
+
+    if report.crawled() {
+        let pages = report.per_url();
+        say!("{GREEN}Visited {BLUE}{}{GREEN} unique pages:{RESET}", pages.len());
+        for (page, page_stats) in pages {
+            say!(
+                "  {BLUE}{page}{RESET}: {} requests, average {:?}, p95 {:?}, max {:?}, {} errors",
+                page_stats.total(),
+                page_stats.mean(),
+                page_stats.percentile(95.0),
+                page_stats.max(),
+                page_stats.fails()
+            );
+        }
+    }
 }
 
 fn get_arguments() -> ArgMatches {
@@ -122,9 +379,52 @@ fn get_arguments() -> ArgMatches {
         ).arg(
             Arg::new("url")
                 .long("url")
-                .value_name("URL")
-                .help("Target URL to test")
-                .required(true),
+                .value_name("URL [WEIGHT]")
+                .help("Target URL to test. Repeat with weights, e.g. --url 'https://example.com/ 70' --url 'https://example.com/search 30', to send a traffic mix")
+                .value_parser(|s: &str| s.parse::<Endpoint>())
+                .action(ArgAction::Append)
+                .required_unless_present_any(["scenario", "url-file"]),
+        )
+        .arg(
+            Arg::new("url-file")
+                .long("url-file")
+                .value_name("PATH")
+                .help("Read the traffic mix from a file with one URL and optional weight per line")
+                .conflicts_with("url"),
+        )
+        .arg(
+            Arg::new("scenario")
+                .long("scenario")
+                .value_name("PATH")
+                .help("Read the whole test plan from a TOML or YAML file instead of the flags that describe the test")
+                .conflicts_with_all([
+                    "url", "url-file", "force-url", "number", "duration", "processes", "rate", "arrival", "stages",
+                    "follow-links", "max-depth", "max-pages", "type", "payload", "header", "bearer",
+                    "basic-auth", "expect-status", "timeout", "ignore-ssl", "csv", "csv-strategy",
+                ]),
+        )
+        .arg(
+            Arg::new("rate")
+                .short('r')
+                .long("rate")
+                .value_name("N/s")
+                .help("Start requests at a fixed rate instead of as fast as the workers allow, e.g. 50 or 50/s or 600/m"),
+        )
+        .arg(
+            Arg::new("arrival")
+                .long("arrival")
+                .help("How requests are spread out with --rate: constant or poisson")
+                .default_value("constant")
+                .value_parser(|s: &str| s.parse::<Arrival>())
+                .requires("rate"),
+        )
+        .arg(
+            Arg::new("stages")
+                .long("stages")
+                .value_name("DURATION:TARGET,...")
+                .help("Ramp the load through stages, e.g. 30s:10,2m:50,30s:0. Targets are workers, or requests/s with --rate")
+                .value_parser(|s: &str| s.parse::<Stages>())
+                .conflicts_with_all(["number", "duration"]),
         )
         .arg(
             Arg::new("follow-links")
@@ -133,13 +433,38 @@ fn get_arguments() -> ArgMatches {
                 .help("Follow hyperlinks on the page")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .help("Links to follow before returning to the start URL")
+                .default_value("5")
+                .value_parser(clap::value_parser!(u32))
+                .requires("follow-links"),
+        )
+        .arg(
+            Arg::new("max-pages")
+                .long("max-pages")
+                .help("Maximum number of unique pages to visit when following links")
+                .default_value("100")
+                .value_parser(clap::value_parser!(usize))
+                .requires("follow-links"),
+        )
+        .arg(
+            Arg::new("duration")
+                .short('d')
+                .long("duration")
+                .value_name("DURATION")
+                .help("Keep sending requests for this long instead of a fixed number, e.g. 90s, 15m, 1h")
+                .value_parser(|s: &str| parse_duration(s))
+                .conflicts_with("number"),
+        )
         .arg(
             Arg::new("number")
                 .short('n')
                 .long("number")
                 .help("Total number of requests to make")
                 .default_value("100")
-                .value_parser(clap::value_parser!(u32)),
+                .value_parser(clap::value_parser!(u64)),
         )
         .arg(
             Arg::new("processes")
@@ -147,14 +472,14 @@ fn get_arguments() -> ArgMatches {
                 .long("processes")
                 .help("Number of concurrent workers")
                 .default_value("10")
-                .value_parser(clap::value_parser!(u32)),
+                .value_parser(clap::value_parser!(u32).range(1..)),
         )
         .arg(
             Arg::new("type")
                 .long("method")
-                .help("HTTP method to use: get or post")
+                .help("HTTP method to use: get, post, put, patch, delete, head, options or a custom upper-case verb")
                 .default_value("get")
-                .value_parser(["get", "post"]),
+                .value_parser(|s: &str| s.parse::<Method>()),
         )
         .arg(
             Arg::new("payload")
@@ -162,6 +487,50 @@ fn get_arguments() -> ArgMatches {
                 .help("Raw JSON or path to file with JSON")
                 .default_value(""),
         )
+        .arg(
+            Arg::new("csv")
+                .long("csv")
+                .value_name("PATH")
+                .help("CSV file with a header line, whose columns the URL, headers and payload can use as {{csv.COLUMN}}"),
+        )
+        .arg(
+            Arg::new("csv-strategy")
+                .long("csv-strategy")
+                .value_name("STRATEGY")
+                .help("How rows of --csv are handed out: sequential, random or unique (each worker has rows of its own)")
+                .default_value("sequential")
+                .value_parser(|s: &str| s.parse::<feeder::Strategy>())
+                .requires("csv"),
+        )
+        .arg(
+            Arg::new("header")
+                .short('H')
+                .long("header")
+                .value_name("NAME: VALUE")
+                .help("Extra header to send with every request. Can be repeated")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("bearer")
+                .long("bearer")
+                .value_name("TOKEN")
+                .help("Send an Authorization: Bearer header with the given token")
+                .conflicts_with("basic-auth"),
+        )
+        .arg(
+            Arg::new("basic-auth")
+                .long("basic-auth")
+                .value_name("USER:PASSWORD")
+                .help("Send an Authorization: Basic header with the given credentials"),
+        )
+        .arg(
+            Arg::new("expect-status")
+                .long("expect-status")
+                .value_name("CODES")
+                .help("Status codes counted as success, e.g. 2xx,3xx or 200-299,404")
+                .default_value("2xx,3xx")
+                .value_parser(|s: &str| s.parse::<ExpectedStatus>()),
+        )
         .arg(
             Arg::new("ignore-ssl")
                 .long("ignore-ssl")
@@ -173,7 +542,7 @@ fn get_arguments() -> ArgMatches {
                 .long("timeout")
                 .help("Timeout for each individual request before failing in seconds")
                 .default_value("10")
-                .value_parser(clap::value_parser!(f32)),
+                .value_parser(clap::value_parser!(f64)),
         )
         .arg(
             Arg::new("verbose")
@@ -182,6 +551,51 @@ fn get_arguments() -> ArgMatches {
                 .help("Verbose output for requests")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Only print the final report, without colour. Skips the confirm step")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .value_name("FORMAT[=PATH]")
+                .help("Also write the report as json, html or junit, to PATH or else to stdout instead of the text report. Can be repeated")
+                .value_parser(|s: &str| s.parse::<ReportOutput>())
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("EXPRESSION")
+                .help("Exit with an error if the results breach this, e.g. 'p95<300ms' or 'error_rate<1%'. Can be repeated")
+                .value_parser(|s: &str| s.parse::<Threshold>())
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("baseline")
+                .long("baseline")
+                .value_name("PATH")
+                .help("Compare the results with a JSON report from an earlier run and exit with an error on a regression"),
+        )
+        .arg(
+            Arg::new("tolerance")
+                .long("tolerance")
+                .value_name("PERCENT")
+                .help("How much worse a metric can get before it counts as a regression. The error rate is compared in percentage points")
+                .default_value("10%")
+                .value_parser(|s: &str| parse_tolerance(s))
+                .global(true),
+        )
+        .arg(
+            Arg::new("raw-csv")
+                .long("raw-csv")
+                .value_name("PATH")
+                .help("Stream one CSV row per request to PATH while the run is going"),
+        )
         .arg(
             Arg::new("force-url")
                 .long("force-url")
@@ -194,10 +608,25 @@ fn get_arguments() -> ArgMatches {
                 .help("None interactive. Skips confirm step")
                 .action(ArgAction::SetTrue)
         )
+        .subcommand(
+            Command::new("compare")
+                .about("Compare two JSON reports written with --output json=PATH")
+                .arg(Arg::new("old").value_name("OLD").help("Report of the earlier run").required(true))
+                .arg(Arg::new("new").value_name("NEW").help("Report of the later run").required(true)),
+        )
+        .subcommand_negates_reqs(true)
         .get_matches()
 }
 
+fn parse_tolerance(s: &str) -> Result<f64, String> {
+    match s.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(tolerance) if tolerance >= 0.0 => Ok(tolerance),
+        _ => Err(format!("Invalid tolerance \"{}\". Use a percentage like 10%", s)),
+    }
+}
+
 fn menu(config: &Config) {
+    let test = &config.test;
     let ascii_banner = format!(
         "{MAGENTA}{BOLD}
      _____ _____ _______ ______ _______ ______  _____ _______ ______ _____  
@@ -210,7 +639,10 @@ fn menu(config: &Config) {
 "
     );
 
-    println!("{}", ascii_banner);
+    // The banner is only for people watching a terminal
+    if COLOUR.load(Ordering::Relaxed) {
+        say!("{}", ascii_banner);
+    }
 
     let mut message = String::new();
     message.push_str(&format!(
@@ -219,57 +651,194 @@ fn menu(config: &Config) {
     message.push_str(&format!(
         "{YELLOW}This application should only be run on websites you have permission from the owner to use.{RESET}\n"
     ));
-    message.push_str(&format!(
-        "{YELLOW}You have selected website {BOLD}{BLUE}{url}{RESET}{YELLOW} to run on.{RESET}\n",
-        url = config.url
-    ));
-    message.push_str(&format!(
-        "{YELLOW}Continuing will make {BOLD}{BLUE}{total_requests}{RESET}{YELLOW} requests \
-        using {BOLD}{BLUE}{total_processes}{RESET}{YELLOW} threads.{RESET}\n",
-        total_requests = config.number,
-        total_processes = config.processes
-    ));
-    if config.timeout != 10000 {
+    if test.endpoints.is_empty() {
+        message.push_str(&format!(
+            "{YELLOW}You have selected website {BOLD}{BLUE}{url}{RESET}{YELLOW} to run on.{RESET}\n",
+            url = test.url
+        ));
+    } else {
+        let total: u64 = test.endpoints.iter().map(|endpoint| u64::from(endpoint.weight)).sum();
+        message.push_str(&format!(
+            "{YELLOW}You have selected a traffic mix of {BOLD}{BLUE}{count}{RESET}{YELLOW} URLs to run on:{RESET}\n",
+            count = test.endpoints.len()
+        ));
+        for endpoint in &test.endpoints {
+            message.push_str(&format!(
+                "  {BOLD}{BLUE}{share:.1}%{RESET}{YELLOW} {url}{RESET}\n",
+                share = 100.0 * endpoint.weight as f64 / total as f64,
+                url = endpoint.url
+            ));
+        }
+    }
+    let profile = &test.profile;
+    match profile.run_length() {
+        RunLength::Requests(total_requests) => message.push_str(&format!(
+            "{YELLOW}Continuing will make {BOLD}{BLUE}{total_requests}{RESET}{YELLOW} requests \
+            using {BOLD}{BLUE}{total_processes}{RESET}{YELLOW} threads.{RESET}\n",
+            total_processes = profile.pool_size()
+        )),
+        RunLength::Duration(duration) => message.push_str(&format!(
+            "{YELLOW}Continuing will send requests for {BOLD}{BLUE}{duration}{RESET}{YELLOW} \
+            using {BOLD}{BLUE}{total_processes}{RESET}{YELLOW} threads.{RESET}\n",
+            duration = format_duration(duration),
+            total_processes = profile.pool_size()
+        )),
+    }
+    if let Some(stages) = &profile.stages {
+        let unit = if profile.rate.is_some() { "requests/s" } else { "workers" };
+        message.push_str(&format!(
+            "{YELLOW}Ramping through stages {BOLD}{BLUE}{stages}{RESET}{YELLOW} ({unit}).{RESET}\n"
+        ));
+    }
+    if let Some(rate) = profile.rate {
+        message.push_str(&format!(
+            "{YELLOW}Starting requests at {BOLD}{BLUE}{rate}{RESET}{YELLOW} with up to \
+            {BOLD}{BLUE}{workers}{RESET}{YELLOW} in flight.{RESET}\n",
+            workers = profile.pool_size()
+        ));
+    }
+    if test.method != Method::Get {
+        message.push_str(&format!(
+            "{YELLOW}Using HTTP method {BOLD}{BLUE}{method}{RESET}{YELLOW}.{RESET}\n",
+            method = test.method
+        ));
+    }
+    if test.timeout != Duration::from_secs(10) {
+        message.push_str(&format!(
+            "{YELLOW}Using custom timeout value of {BOLD}{BLUE}{timeout:?}{RESET}{YELLOW}.{RESET}\n",
+            timeout = test.timeout
+        ));
+    }
+    if test.expected_status != ExpectedStatus::default() {
         message.push_str(&format!(
-            "{YELLOW}Using custom timeout value of {BOLD}{BLUE}{timeout}{RESET}{YELLOW} seconds.{RESET}\n",
-            timeout = config.timeout / 1000
+            "{YELLOW}Only status codes {BOLD}{BLUE}{expected}{RESET}{YELLOW} count as success.{RESET}\n",
+            expected = test.expected_status
         ));
     }
-    if config.ignore_ssl {
+    if test.ignore_ssl {
         message.push_str(&format!("{YELLOW}Ignoring any SSL errors{RESET}\n"));
     }
+    if !test.headers.is_empty() {
+        let names: Vec<&str> = test.headers.keys().map(|name| name.as_str()).collect();
+        message.push_str(&format!(
+            "{YELLOW}Sending headers: {BOLD}{BLUE}{names}{RESET}\n",
+            names = names.join(", ")
+        ));
+    }
+    for feeder in &test.feeders {
+        message.push_str(&format!(
+            "{YELLOW}Reading {BOLD}{BLUE}{rows}{RESET}{YELLOW} rows from feeder {BOLD}{BLUE}{name}{RESET}{YELLOW} \
+            ({strategy}) with columns {columns}.{RESET}\n",
+            rows = feeder.len(),
+            name = feeder.name(),
+            strategy = feeder.strategy(),
+            columns = feeder.columns().join(", ")
+        ));
+    }
+    if let Some(crawl) = test.crawl {
+        message.push_str(&format!(
+            "{YELLOW}Following links up to {BOLD}{BLUE}{depth}{RESET}{YELLOW} deep across at most \
+            {BOLD}{BLUE}{pages}{RESET}{YELLOW} unique pages.{RESET}\n",
+            depth = crawl.max_depth,
+            pages = crawl.max_pages
+        ));
+    }
+    if !test.steps.is_empty() {
+        let names: Vec<&str> = test.steps.iter().map(|step| step.name.as_str()).collect();
+        message.push_str(&format!(
+            "{YELLOW}Each worker goes through the steps {BOLD}{BLUE}{names}{RESET}{YELLOW} in order.{RESET}\n",
+            names = names.join(" -> ")
+        ));
+    } else if let Some(payload) = &test.payload {
+        if test.method.has_body() {
+            message.push_str(&format!(
+                "{YELLOW}Sending a JSON payload of {BOLD}{BLUE}{size}{RESET}{YELLOW} bytes with each request.{RESET}\n",
+                size = payload.len()
+            ));
+        } else {
+            message.push_str(&format!(
+                "{ORANGE}Payload is ignored for {method} requests{RESET}\n",
+                method = test.method
+            ));
+        }
+    }
 
-    println!("{}", message);
+    say!("{}", message);
 
     if atty::is(Stream::Stdin) && atty::is(Stream::Stdout) && ! config.skip_confirm {
         // Interactive mode
-        println!("\n{YELLOW}Press enter to begin or \"exit\" and enter to exit{RESET}");
+        say!("\n{YELLOW}Press enter to begin or \"exit\" and enter to exit{RESET}");
 
         let mut input = String::new();
         io::stdin().read_line(&mut input).expect("Failed to read line");
 
         match input.trim() {
-            "" => println!("\n{YELLOW}Starting Program{RESET}"),
+            "" => say!("\n{YELLOW}Starting Program{RESET}"),
             "exit" => {
-                println!("\n{GREEN}Exiting Gracefully{RESET}");
+                say!("\n{GREEN}Exiting Gracefully{RESET}");
                 exit(0);
             }
             _ => {
-                println!("\n{RED}Unknown Selection{RESET}\n{GREEN}Exiting Gracefully{RESET}");
+                say!("\n{RED}Unknown Selection{RESET}\n{GREEN}Exiting Gracefully{RESET}");
                 exit(0);
             }
         }
     } else {
         // Non-interactive mode (like in your bash script)
-        println!("\n{ORANGE}Non-interactive mode detected, starting program automatically{RESET}\n");
+        say!("\n{ORANGE}Non-interactive mode detected, starting program automatically{RESET}\n");
+    }
+
+}
+
+fn emit(line: String) {
+    if COLOUR.load(Ordering::Relaxed) {
+        println!("{line}");
+    } else {
+        println!("{}", strip_colour(&line));
     }
+}
 
+/// Removes ANSI colour codes such as `\x1b[31m`
+fn strip_colour(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("\x1b[") {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = match rest.find('m') {
+            Some(end) => &rest[end + 1..],
+            None => "",
+        };
+    }
+    plain.push_str(rest);
+    plain
 }
 
+/// Errors go to stderr, so they never end up in a report written to stdout
 fn print_error(message: String) {
-    println!("{RED}ERROR: {message}{RESET}");
+    let line = format!("{RED}ERROR: {message}{RESET}");
+    if COLOUR.load(Ordering::Relaxed) && atty::is(Stream::Stderr) {
+        eprintln!("{line}");
+    } else {
+        eprintln!("{}", strip_colour(&line));
+    }
 }
 
 fn print_message(message: String){
-    println!("{message}");
+    say!("{message}");
+}
+
+fn print_event(event: &Event, verbose: bool) {
+    if event.status() == Some(509) {
+        print_message("Website bandwidth limit reached".to_string());
+    }
+    match event {
+        Event::RequestCompleted { .. } if verbose => print_message(event.to_string()),
+        Event::RequestFailed { .. } if verbose => print_error(event.to_string()),
+        Event::RequestFailed { index, kind: ErrorKind::Status, status: Some(status), .. } => {
+            print_error(format!("Failed Request Number: {} (status {})", index, status));
+        }
+        Event::RequestFailed { index, kind, .. } => print_error(format!("Failed Request Number: {} ({})", index, kind)),
+        _ => {}
+    }
 }
\ No newline at end of file
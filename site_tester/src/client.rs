@@ -0,0 +1,477 @@
+use crate::outcome::{ErrorKind, PhaseTimings};
+use bytes::Bytes;
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Method, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::client::conn::http1::{self, SendRequest};
+use hyper_util::rt::TokioIo;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::crypto::{self, CryptoProvider};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::rustls::{self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use url::{Position, Url};
+
+/// Redirects followed before a request is counted as failed
+const MAX_REDIRECTS: usize = 10;
+
+/// Headers that are not passed on when a redirect leaves the original host
+const SENSITIVE_HEADERS: [header::HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::PROXY_AUTHORIZATION,
+    header::WWW_AUTHENTICATE,
+];
+
+/// HTTP/1.1 client that times each phase of a request: name resolution, TCP
+/// connect, TLS handshake, time to first byte and body download. Shared by all
+/// workers, each of which sends through its own `Session`.
+pub struct Client {
+    timeout: Duration,
+    headers: HeaderMap,
+    tls: TlsConnector,
+}
+
+impl Client {
+    /// `timeout` covers a whole request including redirects and the body.
+    /// `headers` are sent with every request.
+    pub fn new(timeout: Duration, ignore_ssl: bool, headers: HeaderMap) -> Self {
+        Self {
+            timeout,
+            headers,
+            tls: TlsConnector::from(Arc::new(tls_config(ignore_ssl))),
+        }
+    }
+}
+
+/// A response with its body read in full
+#[derive(Debug)]
+pub struct Response {
+    /// Where the response came from, after following any redirects
+    pub url: Url,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Response {
+    pub fn is_html(&self) -> bool {
+        self.headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("text/html"))
+    }
+}
+
+#[derive(Debug)]
+pub struct RequestError {
+    pub kind: ErrorKind,
+    message: String,
+}
+
+impl RequestError {
+    fn new(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+/// One worker's connection to the target. The connection is kept open between
+/// requests, so only the first request to an origin pays for DNS, TCP and TLS.
+pub struct Session {
+    client: Arc<Client>,
+    connection: Option<Connection>,
+}
+
+struct Connection {
+    origin: Origin,
+    sender: SendRequest<Full<Bytes>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Origin {
+    tls: bool,
+    host: String,
+    port: u16,
+}
+
+impl Origin {
+    fn of(url: &Url) -> Result<Self, RequestError> {
+        let tls = match url.scheme() {
+            "https" => true,
+            "http" => false,
+            scheme => return Err(RequestError::new(ErrorKind::Request, format!("unsupported scheme {}", scheme))),
+        };
+        let host = match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(ip)) => ip.to_string(),
+            Some(url::Host::Ipv6(ip)) => ip.to_string(),
+            None => return Err(RequestError::new(ErrorKind::Request, format!("{} has no host", url))),
+        };
+        let port = url.port_or_known_default().unwrap_or(if tls { 443 } else { 80 });
+        Ok(Self { tls, host, port })
+    }
+}
+
+impl Session {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            client,
+            connection: None,
+        }
+    }
+
+    /// Sends a request, following redirects, and reads the whole body. `headers`
+    /// are added to the client's, replacing any with the same name. Time spent
+    /// in each phase is added to `phases`, including for requests that fail.
+    pub async fn send(
+        &mut self,
+        method: Method,
+        url: &str,
+        headers: &HeaderMap,
+        body: Option<String>,
+        phases: &mut PhaseTimings,
+    ) -> Result<Response, RequestError> {
+        let url = Url::parse(url).map_err(|e| RequestError::new(ErrorKind::Request, format!("invalid URL {}: {}", url, e)))?;
+        let timeout = self.client.timeout;
+        let result = match tokio::time::timeout(timeout, self.follow_redirects(method, url, headers, body, phases)).await {
+            Ok(result) => result,
+            Err(_) => Err(RequestError::new(ErrorKind::Timeout, format!("no response within {:?}", timeout))),
+        };
+        if result.is_err() {
+            // The connection could be part way through a response
+            self.connection = None;
+        }
+        result
+    }
+
+    async fn follow_redirects(
+        &mut self,
+        mut method: Method,
+        mut url: Url,
+        extra_headers: &HeaderMap,
+        mut body: Option<String>,
+        phases: &mut PhaseTimings,
+    ) -> Result<Response, RequestError> {
+        let first_origin = Origin::of(&url)?;
+        let mut headers = self.client.headers.clone();
+        headers.extend(extra_headers.clone());
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.send_once(&method, &url, &headers, body.clone(), phases).await?;
+            let location = match response.status {
+                StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT => response.headers.get(header::LOCATION),
+                _ => None,
+            };
+            let Some(location) = location else {
+                return Ok(response);
+            };
+            url = location
+                .to_str()
+                .ok()
+                .and_then(|location| url.join(location).ok())
+                .ok_or_else(|| RequestError::new(ErrorKind::Redirect, "invalid Location header"))?;
+
+            if redirects_to_get(response.status, &method) {
+                method = Method::GET;
+                body = None;
+                headers.remove(header::CONTENT_TYPE);
+            }
+            if Origin::of(&url)? != first_origin {
+                for name in &SENSITIVE_HEADERS {
+                    headers.remove(name);
+                }
+            }
+        }
+        Err(RequestError::new(ErrorKind::Redirect, format!("more than {} redirects", MAX_REDIRECTS)))
+    }
+
+    async fn send_once(
+        &mut self,
+        method: &Method,
+        url: &Url,
+        headers: &HeaderMap,
+        body: Option<String>,
+        phases: &mut PhaseTimings,
+    ) -> Result<Response, RequestError> {
+        let origin = Origin::of(url)?;
+        let body = body.map(Bytes::from).unwrap_or_default();
+        let mut reused = self
+            .connection
+            .as_ref()
+            .is_some_and(|connection| connection.origin == origin && !connection.sender.is_closed());
+
+        let response = loop {
+            if !reused {
+                self.connection = None;
+                self.connection = Some(self.connect(&origin, phases).await?);
+            }
+            let sender = &mut self.connection.as_mut().expect("Connected above").sender;
+            let request = build_request(method, url, headers, body.clone())?;
+
+            let start = Instant::now();
+            let result = match sender.ready().await {
+                Ok(()) => sender.send_request(request).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(response) => {
+                    phases.ttfb += start.elapsed();
+                    break response;
+                }
+                // The server may close an idle connection just as it is reused
+                Err(e) if reused && (e.is_canceled() || e.is_closed() || e.is_incomplete_message()) => {
+                    reused = false;
+                }
+                Err(e) => {
+                    phases.ttfb += start.elapsed();
+                    return Err(RequestError::new(ErrorKind::Request, e));
+                }
+            }
+        };
+
+        let (parts, incoming) = response.into_parts();
+        let start = Instant::now();
+        let body = incoming.collect().await;
+        phases.download += start.elapsed();
+        let body = body.map_err(|e| RequestError::new(ErrorKind::Body, e))?.to_bytes();
+
+        Ok(Response {
+            url: url.clone(),
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+
+    async fn connect(&self, origin: &Origin, phases: &mut PhaseTimings) -> Result<Connection, RequestError> {
+        let start = Instant::now();
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((origin.host.as_str(), origin.port))
+            .await
+            .map_err(|e| RequestError::new(ErrorKind::Connect, format!("failed to resolve {}: {}", origin.host, e)))?
+            .collect();
+        phases.add_dns(start.elapsed());
+
+        let start = Instant::now();
+        let mut last_error = None;
+        let mut stream = None;
+        for address in addresses {
+            match TcpStream::connect(address).await {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        phases.add_connect(start.elapsed());
+        let stream = stream.ok_or_else(|| match last_error {
+            Some(e) => RequestError::new(ErrorKind::Connect, format!("failed to connect to {}: {}", origin.host, e)),
+            None => RequestError::new(ErrorKind::Connect, format!("no addresses found for {}", origin.host)),
+        })?;
+        let _ = stream.set_nodelay(true);
+
+        let sender = if origin.tls {
+            let name = ServerName::try_from(origin.host.clone())
+                .map_err(|e| RequestError::new(ErrorKind::Connect, format!("invalid server name {}: {}", origin.host, e)))?;
+            let start = Instant::now();
+            let stream = self.client.tls.connect(name, stream).await;
+            phases.add_tls(start.elapsed());
+            let stream = stream.map_err(|e| RequestError::new(ErrorKind::Connect, format!("TLS handshake failed: {}", e)))?;
+            handshake(stream).await?
+        } else {
+            handshake(stream).await?
+        };
+
+        Ok(Connection {
+            origin: origin.clone(),
+            sender,
+        })
+    }
+}
+
+async fn handshake<T>(io: T) -> Result<SendRequest<Full<Bytes>>, RequestError>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = http1::handshake(TokioIo::new(io))
+        .await
+        .map_err(|e| RequestError::new(ErrorKind::Connect, e))?;
+    // Drives the connection. Its errors reach us through the pending request.
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    Ok(sender)
+}
+
+/// Whether a redirect turns the request into a GET without a body: a POST
+/// after 301 or 302, as browsers do, and anything but HEAD after 303. Other
+/// methods keep their method and body.
+fn redirects_to_get(status: StatusCode, method: &Method) -> bool {
+    match status {
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => method == Method::POST,
+        StatusCode::SEE_OTHER => method != Method::GET && method != Method::HEAD,
+        _ => false,
+    }
+}
+
+fn build_request(
+    method: &Method,
+    url: &Url,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<http::Request<Full<Bytes>>, RequestError> {
+    let mut request = http::Request::builder()
+        .method(method.clone())
+        .uri(&url[Position::BeforePath..Position::AfterQuery])
+        .body(Full::new(body))
+        .map_err(|e| RequestError::new(ErrorKind::Request, e))?;
+    request.headers_mut().extend(headers.clone());
+    if !request.headers().contains_key(header::HOST) {
+        let host = HeaderValue::from_str(&url[Position::BeforeHost..Position::AfterPort])
+            .map_err(|e| RequestError::new(ErrorKind::Request, e))?;
+        request.headers_mut().insert(header::HOST, host);
+    }
+    Ok(request)
+}
+
+fn tls_config(ignore_ssl: bool) -> ClientConfig {
+    let provider = Arc::new(crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .expect("Default TLS versions are supported");
+    let mut config = if ignore_ssl {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth()
+    } else {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    config
+}
+
+/// Used with `--ignore-ssl`: trusts any certificate but still checks that the
+/// server holds its key
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn requests_use_the_path_and_host_of_the_url() {
+        let url = Url::parse("http://example.com:8080/search?q=1#top").unwrap();
+        let request = build_request(&Method::GET, &url, &HeaderMap::new(), Bytes::new()).unwrap();
+        assert_eq!(request.uri(), "/search?q=1");
+        assert_eq!(request.headers()[header::HOST], "example.com:8080");
+    }
+
+    #[test]
+    fn only_some_redirects_turn_into_a_get() {
+        let rewritten = |status, method: Method| redirects_to_get(status, &method);
+        assert!(rewritten(StatusCode::MOVED_PERMANENTLY, Method::POST));
+        assert!(rewritten(StatusCode::FOUND, Method::POST));
+        assert!(!rewritten(StatusCode::FOUND, Method::PUT));
+        assert!(!rewritten(StatusCode::MOVED_PERMANENTLY, Method::DELETE));
+        assert!(!rewritten(StatusCode::FOUND, Method::PATCH));
+        assert!(rewritten(StatusCode::SEE_OTHER, Method::PUT));
+        assert!(rewritten(StatusCode::SEE_OTHER, Method::DELETE));
+        assert!(!rewritten(StatusCode::SEE_OTHER, Method::HEAD));
+        assert!(!rewritten(StatusCode::TEMPORARY_REDIRECT, Method::POST));
+        assert!(!rewritten(StatusCode::PERMANENT_REDIRECT, Method::POST));
+    }
+
+    #[test]
+    fn times_each_phase_and_reuses_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            for _ in 0..2 {
+                let _ = stream.read(&mut buffer).unwrap();
+                stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello").unwrap();
+            }
+        });
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let client = Arc::new(Client::new(Duration::from_secs(5), false, HeaderMap::new()));
+        let mut session = Session::new(client);
+        let url = format!("http://{}/", address);
+
+        let mut first = PhaseTimings::default();
+        let response = runtime.block_on(session.send(Method::GET, &url, &HeaderMap::new(), None, &mut first)).unwrap();
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(&response.body[..], b"hello");
+        assert!(first.dns.is_some() && first.connect.is_some());
+        assert!(first.tls.is_none());
+
+        let mut second = PhaseTimings::default();
+        runtime.block_on(session.send(Method::GET, &url, &HeaderMap::new(), None, &mut second)).unwrap();
+        assert!(second.dns.is_none() && second.connect.is_none());
+    }
+}
@@ -0,0 +1,365 @@
+use crate::load_test::Report;
+use crate::stats::PERCENTILES;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// p-values below this count as a significant difference
+pub const SIGNIFICANCE: f64 = 0.05;
+
+/// Headline numbers of a run, read back from a JSON report
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    /// Latency metrics in milliseconds, in display order
+    pub latency: Vec<(String, f64)>,
+    pub throughput: f64,
+    /// Percentage of requests that failed
+    pub error_rate: f64,
+    /// Latencies of successful requests in microseconds
+    pub samples: Vec<f64>,
+}
+
+impl RunSummary {
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let number = |value: &Value, name: &str| {
+            value.as_f64().ok_or_else(|| format!("The report has no {}", name))
+        };
+        let total = number(&json["totals"]["requests"], "request total")?;
+        let failures = number(&json["totals"]["failures"], "failure total")?;
+
+        let mut latency = vec![("mean".to_string(), number(&json["latency_ms"]["mean"], "mean latency")?)];
+        for percentile in PERCENTILES {
+            let name = format!("p{}", percentile);
+            let value = number(&json["latency_ms"][&name], &name)?;
+            latency.push((name, value));
+        }
+        latency.push(("max".to_string(), number(&json["latency_ms"]["max"], "maximum latency")?));
+
+        Ok(Self {
+            latency,
+            throughput: number(&json["totals"]["throughput"], "throughput")?,
+            error_rate: if total > 0.0 { failures * 100.0 / total } else { 0.0 },
+            samples: json["samples_us"]
+                .as_array()
+                .map(|samples| samples.iter().filter_map(Value::as_f64).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Reads a report written with `--output json=PATH`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let json: Value =
+            serde_json::from_str(&text).map_err(|e| format!("{} is not a JSON report: {}", path.display(), e))?;
+        Self::from_json(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn from_report(report: &Report) -> Self {
+        let stats = &report.stats;
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut latency = vec![("mean".to_string(), millis(stats.mean()))];
+        for percentile in PERCENTILES {
+            latency.push((format!("p{}", percentile), millis(stats.percentile(percentile))));
+        }
+        latency.push(("max".to_string(), millis(stats.max())));
+
+        Self {
+            latency,
+            throughput: stats.throughput(),
+            error_rate: if stats.total() > 0 { stats.fails() as f64 * 100.0 / stats.total() as f64 } else { 0.0 },
+            samples: report.samples().iter().map(|&sample| sample as f64).collect(),
+        }
+    }
+}
+
+/// How one metric moved between two runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricChange {
+    pub name: String,
+    pub unit: &'static str,
+    pub old: f64,
+    pub new: f64,
+    /// Whether the change is in percentage points rather than relative to the
+    /// old value, for metrics that are already percentages
+    pub points: bool,
+    /// Whether the metric moved the wrong way by more than the tolerance
+    pub regression: bool,
+}
+
+impl MetricChange {
+    /// Relative change in percent, or the difference in percentage points.
+    /// A relative change is infinite when the old value was zero.
+    pub fn change(&self) -> f64 {
+        if self.points {
+            return self.new - self.old;
+        }
+        if self.old == 0.0 {
+            return if self.new == 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        (self.new - self.old) * 100.0 / self.old
+    }
+}
+
+impl fmt::Display for MetricChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:.2}{unit} -> {:.2}{unit}", self.name, self.old, self.new, unit = self.unit)?;
+        let change = self.change();
+        if self.points {
+            write!(f, " ({:+.2} points)", change)?;
+        } else if change.is_finite() {
+            write!(f, " ({:+.1}%)", change)?;
+        } else {
+            f.write_str(" (new)")?;
+        }
+        if self.regression {
+            f.write_str(" REGRESSION")?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of a two-sided Mann-Whitney U test on the raw latencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MannWhitney {
+    pub u: f64,
+    pub p_value: f64,
+    /// Probability that a request from the new run is slower than one from the
+    /// old run. 0.5 means neither is faster.
+    pub slower_probability: f64,
+}
+
+impl MannWhitney {
+    /// None when either side has no samples
+    pub fn test(old: &[f64], new: &[f64]) -> Option<Self> {
+        if old.is_empty() || new.is_empty() {
+            return None;
+        }
+        let (n1, n2) = (old.len() as f64, new.len() as f64);
+        let mut values: Vec<(f64, bool)> = old
+            .iter()
+            .map(|&value| (value, false))
+            .chain(new.iter().map(|&value| (value, true)))
+            .collect();
+        values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Rank sum of the new samples, with tied values sharing their average rank
+        let mut new_rank_sum = 0.0;
+        let mut tie_correction = 0.0;
+        let mut i = 0;
+        while i < values.len() {
+            let mut j = i;
+            while j < values.len() && values[j].0 == values[i].0 {
+                j += 1;
+            }
+            let rank = (i + j + 1) as f64 / 2.0;
+            new_rank_sum += rank * values[i..j].iter().filter(|(_, is_new)| *is_new).count() as f64;
+            let tied = (j - i) as f64;
+            tie_correction += tied * tied * tied - tied;
+            i = j;
+        }
+
+        let u = new_rank_sum - n2 * (n2 + 1.0) / 2.0;
+        let n = n1 + n2;
+        let mean = n1 * n2 / 2.0;
+        let variance = n1 * n2 / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)).max(1.0));
+        let p_value = if variance <= 0.0 {
+            1.0
+        } else {
+            let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+            (2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0)
+        };
+        Some(Self {
+            u,
+            p_value,
+            slower_probability: u / (n1 * n2),
+        })
+    }
+
+    pub fn is_significant(&self) -> bool {
+        self.p_value < SIGNIFICANCE
+    }
+}
+
+/// Differences between an old and a new run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub changes: Vec<MetricChange>,
+    pub significance: Option<MannWhitney>,
+}
+
+impl Comparison {
+    /// Compares `new` against `old`. A metric regresses when it gets worse by
+    /// more than `tolerance` percent, or by more than `tolerance` percentage
+    /// points for the error rate; latencies also need the Mann-Whitney test
+    /// to show the new run is significantly slower, when samples are available.
+    pub fn new(old: &RunSummary, new: &RunSummary, tolerance: f64) -> Self {
+        let significance = MannWhitney::test(&old.samples, &new.samples);
+        let slower = significance.is_none_or(|test| test.is_significant() && test.slower_probability > 0.5);
+
+        let mut changes: Vec<MetricChange> = old
+            .latency
+            .iter()
+            .filter_map(|(name, old_value)| {
+                let (_, new_value) = new.latency.iter().find(|(new_name, _)| new_name == name)?;
+                Some(MetricChange {
+                    name: name.clone(),
+                    unit: "ms",
+                    old: *old_value,
+                    new: *new_value,
+                    points: false,
+                    regression: false,
+                })
+            })
+            .collect();
+        for change in &mut changes {
+            change.regression = slower && change.change() > tolerance;
+        }
+
+        let mut throughput = MetricChange {
+            name: "throughput".to_string(),
+            unit: "/s",
+            old: old.throughput,
+            new: new.throughput,
+            points: false,
+            regression: false,
+        };
+        throughput.regression = -throughput.change() > tolerance;
+        let mut error_rate = MetricChange {
+            name: "error rate".to_string(),
+            unit: "%",
+            old: old.error_rate,
+            new: new.error_rate,
+            points: true,
+            regression: false,
+        };
+        error_rate.regression = error_rate.change() > tolerance;
+        changes.push(throughput);
+        changes.push(error_rate);
+
+        Self { changes, significance }
+    }
+
+    pub fn has_regression(&self) -> bool {
+        self.changes.iter().any(|change| change.regression)
+    }
+
+    /// Describes the significance test result, if there was one
+    pub fn significance_line(&self) -> Option<String> {
+        self.significance.map(|test| {
+            let verdict = match (test.is_significant(), test.slower_probability > 0.5) {
+                (false, _) => "no significant difference in latency",
+                (true, true) => "latency is significantly higher",
+                (true, false) => "latency is significantly lower",
+            };
+            format!(
+                "Mann-Whitney U test: {} (p = {:.4}, new request slower {:.1}% of the time)",
+                verdict,
+                test.p_value,
+                test.slower_probability * 100.0
+            )
+        })
+    }
+}
+
+/// Standard normal cumulative distribution function
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Error function, accurate to about 1e-7 (Abramowitz and Stegun 7.1.26)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = t
+        * (0.254_829_592 + t * (-0.284_496_736 + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let value = 1.0 - polynomial * (-x * x).exp();
+    if x < 0.0 { -value } else { value }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(scale: f64, samples: Vec<f64>, error_rate: f64) -> RunSummary {
+        RunSummary {
+            latency: vec![("mean".to_string(), 10.0 * scale), ("p95".to_string(), 20.0 * scale)],
+            throughput: 100.0 / scale,
+            error_rate,
+            samples,
+        }
+    }
+
+    #[test]
+    fn summaries_of_reports_match_their_json() {
+        use crate::load_test::LoadTestConfig;
+        use crate::outcome::{ErrorKind, RequestOutcome};
+
+        let mut report = Report::new(LoadTestConfig::new("http://localhost/"), std::time::UNIX_EPOCH);
+        for (i, error) in [None, None, Some(ErrorKind::Timeout)].into_iter().enumerate() {
+            report.record(&RequestOutcome {
+                index: i as u64,
+                worker: 0,
+                url: "http://localhost/".to_string(),
+                step: None,
+                endpoint: None,
+                start: Duration::from_millis(i as u64 * 100),
+                scheduled: None,
+                duration: Duration::from_millis(10 * (i as u64 + 1)),
+                status: error.is_none().then_some(200),
+                bytes: 2,
+                error,
+                phases: Default::default(),
+            });
+        }
+        let json = crate::output::json_report(&report);
+        assert_eq!(RunSummary::from_report(&report), RunSummary::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn identical_samples_are_not_significant() {
+        let samples: Vec<f64> = (0..50).map(f64::from).collect();
+        let test = MannWhitney::test(&samples, &samples).unwrap();
+        assert!(!test.is_significant());
+        assert!((test.slower_probability - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn shifted_samples_are_significant() {
+        let old: Vec<f64> = (0..50).map(f64::from).collect();
+        let new: Vec<f64> = old.iter().map(|value| value + 30.0).collect();
+        let test = MannWhitney::test(&old, &new).unwrap();
+        assert!(test.is_significant());
+        assert!(test.slower_probability > 0.9);
+        assert!(MannWhitney::test(&old, &[]).is_none());
+    }
+
+    #[test]
+    fn slower_runs_are_flagged_beyond_the_tolerance() {
+        let old_samples: Vec<f64> = (0..50).map(f64::from).collect();
+        let new_samples: Vec<f64> = old_samples.iter().map(|value| value * 2.0 + 10.0).collect();
+        let comparison = Comparison::new(&summary(1.0, old_samples.clone(), 0.0), &summary(1.5, new_samples, 0.0), 10.0);
+        assert!(comparison.has_regression());
+        let p95 = comparison.changes.iter().find(|change| change.name == "p95").unwrap();
+        assert!(p95.regression);
+        assert_eq!(p95.to_string(), "p95: 20.00ms -> 30.00ms (+50.0%) REGRESSION");
+
+        // Same latencies, within tolerance
+        let comparison = Comparison::new(&summary(1.0, old_samples.clone(), 0.0), &summary(1.05, old_samples, 0.0), 10.0);
+        assert!(!comparison.has_regression());
+    }
+
+    #[test]
+    fn error_rates_are_compared_in_percentage_points() {
+        let comparison = Comparison::new(&summary(1.0, vec![], 0.0), &summary(1.0, vec![], 2.0), 1.0);
+        let error_rate = comparison.changes.last().unwrap();
+        assert!(error_rate.regression);
+        assert_eq!(error_rate.to_string(), "error rate: 0.00% -> 2.00% (+2.00 points) REGRESSION");
+
+        // A single failure against a clean baseline is within any sensible tolerance
+        let comparison = Comparison::new(&summary(1.0, vec![], 0.0), &summary(1.0, vec![], 0.01), 1.0);
+        assert!(!comparison.has_regression());
+        let comparison = Comparison::new(&summary(1.0, vec![], 4.0), &summary(1.0, vec![], 4.5), 1.0);
+        assert!(!comparison.has_regression());
+    }
+}
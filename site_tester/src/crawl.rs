@@ -0,0 +1,182 @@
+use rand::seq::IndexedRandom;
+use url::Url;
+use scraper::{Html, Selector};
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex};
+
+static LINKS: LazyLock<Selector> = LazyLock::new(|| Selector::parse("a[href]").expect("A valid selector"));
+
+#[derive(Debug, Clone, Copy)]
+pub struct CrawlOptions {
+    /// Number of links a worker follows before returning to the start URL
+    pub max_depth: u32,
+    /// Maximum number of unique pages visited across all workers
+    pub max_pages: usize,
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_pages: 100,
+        }
+    }
+}
+
+/// Shared between worker threads. Decides which page each worker visits next
+/// and keeps track of the unique pages visited.
+pub struct Crawler {
+    root: Url,
+    options: CrawlOptions,
+    pages: Mutex<HashSet<String>>,
+}
+
+impl Crawler {
+    pub fn new(root: &str, options: CrawlOptions) -> Result<Self, String> {
+        let root = Url::parse(root).map_err(|e| format!("Invalid URL {}: {}", root, e))?;
+        let mut pages = HashSet::new();
+        pages.insert(root.to_string());
+        Ok(Self {
+            root,
+            options,
+            pages: Mutex::new(pages),
+        })
+    }
+
+    pub fn root(&self) -> &Url {
+        &self.root
+    }
+
+    /// Whether a worker `depth` links away from the start URL follows another
+    /// link, so the links of the page it is on are needed
+    pub fn follows_links(&self, depth: u32) -> bool {
+        depth < self.options.max_depth
+    }
+
+    /// Picks the next page from the `links` of the page a worker is on. Falls
+    /// back to the start URL once `depth` reaches the limit or there is nothing
+    /// left to follow.
+    pub fn next_url(&self, depth: u32, links: &[Url]) -> (Url, u32) {
+        if !self.follows_links(depth) {
+            return (self.root.clone(), 0);
+        }
+
+        let mut pages = self.pages.lock().unwrap();
+        let budget_left = pages.len() < self.options.max_pages;
+        let candidates: Vec<&Url> = links
+            .iter()
+            .filter(|link| link.origin() == self.root.origin())
+            .filter(|link| budget_left || pages.contains(link.as_str()))
+            .collect();
+
+        match candidates.choose(&mut rand::rng()) {
+            Some(&next) => {
+                pages.insert(next.to_string());
+                (next.clone(), depth + 1)
+            }
+            None => (self.root.clone(), 0),
+        }
+    }
+}
+
+/// Returns the unique same-origin http(s) links in `html`, resolved against `base`
+pub fn extract_links(base: &Url, html: &str) -> Vec<Url> {
+    let document = Html::parse_document(html);
+    let mut links: Vec<Url> = Vec::new();
+    for element in document.select(&LINKS) {
+        let Some(href) = element.value().attr("href") else {
+            continue;
+        };
+        let Ok(mut link) = base.join(href.trim()) else {
+            continue;
+        };
+        link.set_fragment(None);
+        if link.origin() == base.origin() && !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn next_url(crawler: &Crawler, base: &Url, depth: u32, html: &str) -> (Url, u32) {
+        crawler.next_url(depth, &extract_links(base, html))
+    }
+
+    #[test]
+    fn only_same_origin_links_are_kept() {
+        let html = r#"
+            <a href="/a">a</a>
+            <a href="https://example.com/b">b</a>
+            <a href="https://other.com/c">other host</a>
+            <a href="http://example.com/d">other scheme</a>
+            <a href="https://example.com:8443/e">other port</a>
+            <a href="mailto:me@example.com">mail</a>
+            <a>no href</a>"#;
+        let links = extract_links(&url("https://example.com/"), html);
+        assert_eq!(links, vec![url("https://example.com/a"), url("https://example.com/b")]);
+    }
+
+    #[test]
+    fn relative_and_fragment_links_are_resolved() {
+        let html = r##"
+            <a href="next">relative</a>
+            <a href="../up">parent</a>
+            <a href="  /spaced  ">spaced</a>
+            <a href="#top">fragment only</a>
+            <a href="next#section">same page twice</a>"##;
+        let links = extract_links(&url("https://example.com/docs/page"), html);
+        assert_eq!(
+            links,
+            vec![
+                url("https://example.com/docs/next"),
+                url("https://example.com/up"),
+                url("https://example.com/spaced"),
+                url("https://example.com/docs/page"),
+            ]
+        );
+    }
+
+    #[test]
+    fn workers_go_back_to_the_start_at_the_depth_limit() {
+        let crawler = Crawler::new("https://example.com/", CrawlOptions { max_depth: 2, max_pages: 100 }).unwrap();
+        let html = r#"<a href="/next">next</a>"#;
+        assert_eq!(next_url(&crawler, crawler.root(), 0, html), (url("https://example.com/next"), 1));
+        assert_eq!(next_url(&crawler, crawler.root(), 1, html), (url("https://example.com/next"), 2));
+        assert_eq!(next_url(&crawler, crawler.root(), 2, html), (url("https://example.com/"), 0));
+        // Nothing to follow
+        assert_eq!(next_url(&crawler, crawler.root(), 0, "<p>no links</p>"), (url("https://example.com/"), 0));
+    }
+
+    #[test]
+    fn only_visited_pages_are_followed_once_max_pages_is_reached() {
+        let crawler = Crawler::new("https://example.com/", CrawlOptions { max_depth: 10, max_pages: 2 }).unwrap();
+        let (first, _) = next_url(&crawler, crawler.root(), 0, r#"<a href="/one">one</a>"#);
+        assert_eq!(first, url("https://example.com/one"));
+
+        // The budget is used up, so only /one can be picked again
+        let html = r#"<a href="/two">two</a><a href="/one">one</a><a href="/three">three</a>"#;
+        for _ in 0..20 {
+            assert_eq!(next_url(&crawler, crawler.root(), 0, html).0, first);
+        }
+        let (next, depth) = next_url(&crawler, crawler.root(), 0, r#"<a href="/two">two</a>"#);
+        assert_eq!((next, depth), (url("https://example.com/"), 0));
+    }
+
+    #[test]
+    fn links_are_resolved_against_the_page_after_redirects() {
+        let crawler = Crawler::new("https://example.com/old", CrawlOptions::default()).unwrap();
+        let (next, _) = next_url(&crawler, &url("https://example.com/new/"), 0, r#"<a href="page">page</a>"#);
+        assert_eq!(next, url("https://example.com/new/page"));
+        // A redirect to another site is not followed further
+        let (next, _) = next_url(&crawler, &url("https://other.com/"), 0, r#"<a href="page">page</a>"#);
+        assert_eq!(next, url("https://example.com/old"));
+    }
+}
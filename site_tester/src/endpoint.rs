@@ -0,0 +1,148 @@
+use std::path::Path;
+use std::str::FromStr;
+
+/// One URL of a traffic mix, such as the homepage for 70% of the requests and
+/// search for the other 30%. Weights are relative to each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub url: String,
+    pub weight: u32,
+}
+
+impl Endpoint {
+    pub fn new(url: impl Into<String>, weight: u32) -> Self {
+        Self { url: url.into(), weight }
+    }
+
+    /// Reads one endpoint per line, as `URL [WEIGHT]`. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Vec<Self>, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let endpoints = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| line.parse().map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e)))
+            .collect::<Result<Vec<Self>, _>>()?;
+        if endpoints.is_empty() {
+            return Err(format!("{}: no URLs in the file", path.display()));
+        }
+        Ok(endpoints)
+    }
+}
+
+/// `URL` or `URL WEIGHT`, e.g. `https://example.com/search 20`. The weight
+/// defaults to 1 and can be written as a percentage such as `20%`. A last word
+/// that looks like a number is taken as the weight, so a typo such as `2O` is an
+/// error rather than part of the URL.
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (url, weight) = match s.rsplit_once(char::is_whitespace) {
+            Some((url, weight)) if looks_numeric(weight) => {
+                let weight = weight
+                    .strip_suffix('%')
+                    .unwrap_or(weight)
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid weight {} for {}", weight, url.trim()))?;
+                (url.trim(), weight)
+            }
+            _ => (s, 1),
+        };
+        if url.is_empty() {
+            return Err(format!("Endpoint \"{}\" has no URL", s));
+        }
+        if weight == 0 {
+            return Err(format!("Weight of {} must be at least 1", url));
+        }
+        Ok(Self::new(url, weight))
+    }
+}
+
+/// Starts with a digit and has nothing but letters, digits, `.`, `,` and `%`,
+/// unlike the end of a template such as `{{randInt 1 5}}`
+fn looks_numeric(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_digit())
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | ',' | '%'))
+}
+
+/// Picks endpoints at random in proportion to their weights
+#[derive(Debug, Clone)]
+pub(crate) struct Mix {
+    /// Running total of the weights, ending with the sum of them all
+    cumulative: Vec<u64>,
+}
+
+impl Mix {
+    pub fn new(endpoints: &[Endpoint]) -> Self {
+        let cumulative = endpoints
+            .iter()
+            .scan(0, |total, endpoint| {
+                *total += u64::from(endpoint.weight);
+                Some(*total)
+            })
+            .collect();
+        Self { cumulative }
+    }
+
+    /// Index of the endpoint for a number drawn from `0..total`
+    fn index(&self, draw: u64) -> usize {
+        self.cumulative.partition_point(|&total| total <= draw)
+    }
+
+    pub fn pick(&self) -> usize {
+        match self.cumulative.last() {
+            Some(&total) if total > 0 => self.index(rand::random_range(0..total)),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_are_parsed_with_weights() {
+        assert_eq!("https://example.com/".parse(), Ok(Endpoint::new("https://example.com/", 1)));
+        assert_eq!("https://example.com/search  20".parse(), Ok(Endpoint::new("https://example.com/search", 20)));
+        assert_eq!("example.com 70%".parse(), Ok(Endpoint::new("example.com", 70)));
+        assert_eq!(
+            "http://localhost/?n={{randInt 1 5}}".parse(),
+            Ok(Endpoint::new("http://localhost/?n={{randInt 1 5}}", 1))
+        );
+        assert_eq!("http://localhost/ 0".parse::<Endpoint>(), Err("Weight of http://localhost/ must be at least 1".to_string()));
+        assert_eq!("http://localhost/ 0%".parse::<Endpoint>(), Err("Weight of http://localhost/ must be at least 1".to_string()));
+        assert!("".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn only_numbers_are_taken_as_weights() {
+        let invalid = |s: &str| s.parse::<Endpoint>().unwrap_err();
+        assert_eq!(invalid("https://x/ 2O"), "Invalid weight 2O for https://x/");
+        assert_eq!(invalid("https://x/ 5000000000"), "Invalid weight 5000000000 for https://x/");
+        assert_eq!(invalid("https://x/ 20%%"), "Invalid weight 20%% for https://x/");
+        assert_eq!(invalid("https://x/ 1.5"), "Invalid weight 1.5 for https://x/");
+        assert_eq!(invalid("https://x/ 1,000"), "Invalid weight 1,000 for https://x/");
+        // Words that are not numbers are part of the URL, as before
+        assert_eq!("https://x/ -3".parse(), Ok(Endpoint::new("https://x/ -3", 1)));
+        assert_eq!("https://x/ abc".parse(), Ok(Endpoint::new("https://x/ abc", 1)));
+    }
+
+    #[test]
+    fn endpoints_are_picked_by_weight() {
+        let mix = Mix::new(&[Endpoint::new("a", 7), Endpoint::new("b", 2), Endpoint::new("c", 1)]);
+        let picks: Vec<usize> = [0, 6, 7, 8, 9].into_iter().map(|draw| mix.index(draw)).collect();
+        assert_eq!(picks, vec![0, 0, 1, 1, 2]);
+
+        let mut counts = [0; 3];
+        for _ in 0..10_000 {
+            counts[mix.pick()] += 1;
+        }
+        assert!((6_500..7_500).contains(&counts[0]), "{:?}", counts);
+        assert!((500..1_500).contains(&counts[2]), "{:?}", counts);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::client::{Client, Session};
+use crate::crawl::{Crawler, extract_links};
+use crate::events::Event;
+use crate::journey::Journey;
+use crate::load_test::LoadTestConfig;
+use crate::outcome::{ErrorKind, PhaseTimings, RequestOutcome};
+use crate::profile::{LoadProfile, RunLength, Schedule};
+use crate::template::{Scope, Templates};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, mpsc::Sender};
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+/// Longest a waiting worker sleeps before checking for cancellation again
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Events queued for the subscribers before workers wait for room
+const EVENT_QUEUE: usize = 10_000;
+
+/// Everything the workers of one run share
+struct Run {
+    config: LoadTestConfig,
+    templates: Templates,
+    length: RunLength,
+    schedule: Option<Schedule>,
+    client: Arc<Client>,
+    crawler: Option<Arc<Crawler>>,
+    cancel_flag: Arc<AtomicBool>,
+    run_start: Instant,
+    /// Hands out request indices when the number of requests is not known up front
+    next_index: AtomicU64,
+    /// Passes events to the thread that calls the subscribers, when there are any
+    events: Option<tokio::sync::mpsc::Sender<Event>>,
+}
+
+impl Run {
+    /// Queues `event` for the subscribers, so a slow one does not skew the
+    /// timings. No event is dropped, since a samples CSV needs every request:
+    /// once the queue is full, workers wait for room, which slows the run down
+    /// rather than letting the queue use up memory.
+    async fn emit(&self, event: Event) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event).await;
+        }
+    }
+}
+
+/// Starts a run in the background and returns straight away. Workers are tokio
+/// tasks on a runtime owned by a single background thread, so a large number of
+/// workers does not mean a large number of OS threads. Each finished request is
+/// sent to `results`; the channel disconnects once every worker has stopped.
+pub(crate) fn spawn(
+    config: LoadTestConfig,
+    templates: Templates,
+    client: Arc<Client>,
+    crawler: Option<Arc<Crawler>>,
+    results: Sender<RequestOutcome>,
+    cancel_flag: Arc<AtomicBool>,
+) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to start the async runtime");
+    let workers = config.profile.pool_size();
+    let (events, dispatcher) = if config.subscribers.is_empty() {
+        (None, None)
+    } else {
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<Event>(EVENT_QUEUE);
+        let subscribers = config.subscribers.clone();
+        let dispatcher = std::thread::spawn(move || {
+            while let Some(event) = receiver.blocking_recv() {
+                for subscriber in &subscribers {
+                    subscriber.on_event(&event);
+                }
+            }
+        });
+        (Some(sender), Some(dispatcher))
+    };
+    let run = Arc::new(Run {
+        length: config.profile.run_length(),
+        schedule: config.profile.schedule(),
+        config,
+        templates,
+        client,
+        crawler,
+        cancel_flag,
+        run_start: Instant::now(),
+        next_index: AtomicU64::new(0),
+        events,
+    });
+
+    std::thread::spawn(move || {
+        runtime.block_on(async {
+            let mut tasks = JoinSet::new();
+            for worker in 0..workers {
+                tasks.spawn(run_worker(Arc::clone(&run), worker, workers, results.clone()));
+            }
+            while tasks.join_next().await.is_some() {}
+            run.emit(Event::RunFinished { elapsed: run.run_start.elapsed() }).await;
+        });
+        // Subscribers see every event before the run counts as over
+        drop(run);
+        if let Some(dispatcher) = dispatcher {
+            let _ = dispatcher.join();
+        }
+        // Dropping the last sender tells the receiver the run is over
+        drop(results);
+    });
+}
+
+async fn run_worker(run: Arc<Run>, worker: u32, workers: u32, results: Sender<RequestOutcome>) {
+    let config = &run.config;
+    let (start_idx, requests_for_this_worker) = match (&run.schedule, run.length) {
+        (None, RunLength::Requests(number)) => split_requests(number, workers, worker),
+        _ => (0, u64::MAX),
+    };
+    // Closed-model stages change the number of active workers over time
+    let staged_workers = config.profile.stages.is_some() && config.profile.rate.is_none();
+    let mut session = Session::new(Arc::clone(&run.client));
+    // Page this worker is on and how many links it has followed to get there
+    let mut page = run.crawler.as_ref().map(|c| (c.root().clone(), 0));
+    let mut journey = (!config.steps.is_empty()).then(Journey::default);
+    // Feeder rows taken for the current request, or for the whole journey
+    let mut rows = HashMap::new();
+
+    for j in 0..requests_for_this_worker {
+        if run.cancel_flag.load(Ordering::SeqCst) {
+            break;
+        }
+        if staged_workers && !wait_until_active(worker, &config.profile, run.run_start, &run.cancel_flag).await {
+            break;
+        }
+        let (idx, scheduled) = match (&run.schedule, run.length) {
+            (Some(schedule), _) => {
+                let Some((index, at)) = schedule.next_slot() else {
+                    break;
+                };
+                if !sleep_until(run.run_start + at, &run.cancel_flag).await {
+                    break;
+                }
+                (index, Some(at))
+            }
+            (None, RunLength::Requests(_)) => (start_idx + j, None),
+            (None, RunLength::Duration(duration)) => {
+                if run.run_start.elapsed() >= duration {
+                    break;
+                }
+                (run.next_index.fetch_add(1, Ordering::Relaxed), None)
+            }
+        };
+        let step = journey.as_ref().map(|journey| (journey.step(), &config.steps[journey.step()]));
+        if step.is_none_or(|(index, _)| index == 0) {
+            rows.clear();
+        }
+        let mut scope = Scope {
+            index: idx,
+            worker,
+            workers,
+            feeders: &config.feeders,
+            variables: journey.as_ref().map(Journey::variables),
+            rows: &mut rows,
+        };
+        let templates = &run.templates;
+        let endpoint = (!config.endpoints.is_empty()).then(|| templates.mix.pick());
+        let target = &templates.targets[endpoint.unwrap_or(0)];
+        let request = match step {
+            Some((index, _)) => target
+                .base(&mut scope)
+                .and_then(|base| templates.steps[index].render(Some(&base), &mut scope)),
+            None => target.render(None, &mut scope).map(|mut request| {
+                if let Some((page_url, _)) = &page {
+                    request.url = page_url.to_string();
+                }
+                request
+            }),
+        };
+        let expected_status = step
+            .and_then(|(_, step)| step.expected_status.as_ref())
+            .unwrap_or(&config.expected_status);
+        if let Ok(request) = &request {
+            // Before the clock starts, so waiting for room in the event queue is not timed
+            run.emit(Event::RequestStarted {
+                index: idx,
+                worker,
+                url: request.url.clone(),
+            })
+            .await;
+        }
+        let start = Instant::now();
+
+        let mut outcome = RequestOutcome {
+            index: idx,
+            worker,
+            url: match &request {
+                Ok(request) => request.url.clone(),
+                Err(_) => config.url.clone(),
+            },
+            step: step.map(|(index, _)| index),
+            endpoint,
+            start: start - run.run_start,
+            scheduled,
+            duration: Duration::ZERO,
+            status: None,
+            bytes: 0,
+            error: None,
+            phases: PhaseTimings::default(),
+        };
+        let mut html = None;
+        let mut message = None;
+
+        let result = match request {
+            Ok(request) => Some(
+                session
+                    .send(request.method, &request.url, &request.headers, request.body, &mut outcome.phases)
+                    .await,
+            ),
+            Err(e) => {
+                // A request that cannot be filled in is never sent
+                outcome.error = Some(ErrorKind::Request);
+                message = Some(e);
+                None
+            }
+        };
+        outcome.duration = start.elapsed();
+        match result {
+            Some(Ok(response)) => {
+                outcome.status = Some(response.status.as_u16());
+                outcome.bytes = response.body.len() as u64;
+                let follows_links = match (&run.crawler, &page) {
+                    (Some(crawler), Some((_, depth))) => crawler.follows_links(*depth),
+                    _ => false,
+                };
+                if response.is_html() && follows_links {
+                    html = Some((response.url.clone(), response.body.clone()));
+                }
+                if !expected_status.matches(response.status.as_u16()) {
+                    outcome.error = Some(ErrorKind::Status);
+                } else if let (Some(journey), Some((_, step))) = (&mut journey, step)
+                    && let Err(e) = journey.extract(step, &response)
+                {
+                    outcome.error = Some(ErrorKind::Extract);
+                    message = Some(e);
+                }
+            }
+            Some(Err(e)) => {
+                outcome.error = Some(e.kind);
+                message = Some(e.message().to_string());
+            }
+            None => {}
+        }
+        run.emit(match (outcome.error, outcome.status) {
+            (None, Some(status)) => Event::RequestCompleted {
+                index: idx,
+                worker,
+                url: outcome.url.clone(),
+                start: outcome.start,
+                status,
+                duration: outcome.duration,
+                bytes: outcome.bytes,
+                phases: outcome.phases,
+            },
+            (kind, status) => Event::RequestFailed {
+                index: idx,
+                worker,
+                url: outcome.url.clone(),
+                start: outcome.start,
+                kind: kind.unwrap_or(ErrorKind::Other),
+                status,
+                duration: outcome.duration,
+                bytes: outcome.bytes,
+                message,
+            },
+        })
+        .await;
+
+        if let Some(journey) = &mut journey {
+            journey.advance(config.steps.len(), outcome.is_success());
+        }
+        if let (Some(crawler), Some((_, depth))) = (&run.crawler, &page) {
+            let links = match html {
+                // Parsing is CPU-bound, so it is kept off the threads sending
+                // requests. Links are relative to where any redirects ended up.
+                Some((base, body)) if outcome.is_success() => {
+                    tokio::task::spawn_blocking(move || extract_links(&base, &String::from_utf8_lossy(&body)))
+                        .await
+                        .unwrap_or_default()
+                }
+                _ => Vec::new(),
+            };
+            page = Some(crawler.next_url(*depth, &links));
+        }
+
+        if results.send(outcome).is_err() {
+            // Nobody is listening for results any more
+            break;
+        }
+    }
+    run.emit(Event::WorkerFinished { worker }).await;
+}
+
+/// Parks an idle worker until the load profile needs it. Returns false if the
+/// run ended or was cancelled first.
+async fn wait_until_active(worker: u32, profile: &LoadProfile, run_start: Instant, cancel_flag: &AtomicBool) -> bool {
+    let end = match profile.run_length() {
+        RunLength::Duration(duration) => duration,
+        RunLength::Requests(_) => return true,
+    };
+    loop {
+        let elapsed = run_start.elapsed();
+        if cancel_flag.load(Ordering::SeqCst) || elapsed >= end {
+            return false;
+        }
+        if worker < profile.active_workers(elapsed) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(50).min(end - elapsed)).await;
+    }
+}
+
+/// Sleeps until `deadline`, waking up regularly to check for cancellation.
+/// Returns false if the run was cancelled while waiting.
+async fn sleep_until(deadline: Instant, cancel_flag: &AtomicBool) -> bool {
+    loop {
+        if cancel_flag.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        tokio::time::sleep((deadline - now).min(CANCEL_POLL)).await;
+    }
+}
+
+/// First request index and number of requests for worker `worker` when
+/// `number` requests are shared between `workers` workers.
+fn split_requests(number: u64, workers: u32, worker: u32) -> (u64, u64) {
+    let workers = u64::from(workers.max(1));
+    let worker = u64::from(worker);
+    let per_worker = number / workers;
+    let remainder = number % workers;
+    let count = per_worker + if worker < remainder { 1 } else { 0 };
+    let start = worker * per_worker + worker.min(remainder);
+    (start, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_split_between_workers() {
+        let splits: Vec<_> = (0..3).map(|i| split_requests(10, 3, i)).collect();
+        assert_eq!(splits, vec![(0, 4), (4, 3), (7, 3)]);
+    }
+
+    #[test]
+    fn request_split_handles_counts_over_u32() {
+        let number = u64::from(u32::MAX) * 3;
+        let (start, count) = split_requests(number, 2, 1);
+        assert_eq!(start + count, number);
+        assert_eq!(count, number / 2);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::outcome::{ErrorKind, PhaseTimings};
+use std::fmt;
+use std::time::Duration;
+
+/// Something that happened during a run. Sent to every `Subscriber` in the order it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A request is about to go out. Requests whose templates cannot be
+    /// filled in are never sent, so they only have a `RequestFailed`.
+    RequestStarted {
+        index: u64,
+        worker: u32,
+        url: String,
+    },
+    /// A response arrived with an expected status code
+    RequestCompleted {
+        index: u64,
+        worker: u32,
+        url: String,
+        /// When the request was sent, since the start of the run
+        start: Duration,
+        status: u16,
+        duration: Duration,
+        bytes: u64,
+        phases: PhaseTimings,
+    },
+    /// The request failed or the response had an unexpected status code
+    RequestFailed {
+        index: u64,
+        worker: u32,
+        url: String,
+        start: Duration,
+        kind: ErrorKind,
+        /// Set when a response arrived
+        status: Option<u16>,
+        duration: Duration,
+        bytes: u64,
+        /// Details of a transport error
+        message: Option<String>,
+    },
+    /// A worker has no more requests to send
+    WorkerFinished { worker: u32 },
+    /// Every worker has finished
+    RunFinished { elapsed: Duration },
+}
+
+impl Event {
+    /// Status code of the response, for request events that got one
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Event::RequestCompleted { status, .. } => Some(*status),
+            Event::RequestFailed { status, .. } => *status,
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::RequestStarted { index, worker, url } => {
+                write!(f, "Request {} (worker {}) {}: started", index, worker, url)
+            }
+            Event::RequestCompleted { index, worker, url, status, duration, bytes, phases, .. } => write!(
+                f,
+                "Request {} (worker {}) {}: status {} in {:?}, {} bytes ({})",
+                index, worker, url, status, duration, bytes, phases
+            ),
+            Event::RequestFailed { index, worker, url, kind, status, duration, message, .. } => {
+                write!(f, "Request {} (worker {}) {}: ", index, worker, url)?;
+                match (kind, status) {
+                    (ErrorKind::Status, Some(status)) => {
+                        write!(f, "failed with unexpected status {} in {:?}", status, duration)
+                    }
+                    _ => {
+                        write!(f, "failed with {} error after {:?}", kind, duration)?;
+                        match message {
+                            Some(message) => write!(f, ": {}", message),
+                            None => Ok(()),
+                        }
+                    }
+                }
+            }
+            Event::WorkerFinished { worker } => write!(f, "Worker {} finished", worker),
+            Event::RunFinished { elapsed } => write!(f, "Run finished after {:?}", elapsed),
+        }
+    }
+}
+
+/// Receives the events of a run. Called from a thread of its own, away from the
+/// workers, so a slow subscriber delays later events but not the requests.
+/// Any `Fn(&Event)` closure is a subscriber.
+pub trait Subscriber: Send + Sync {
+    fn on_event(&self, event: &Event);
+}
+
+impl<F> Subscriber for F
+where
+    F: Fn(&Event) + Send + Sync,
+{
+    fn on_event(&self, event: &Event) {
+        self(event)
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How a feeder hands out its rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// In file order across all workers, starting again after the last row
+    #[default]
+    Sequential,
+    /// Any row, picked at random for each request
+    Random,
+    /// Each worker has rows of its own, so no two workers share a row
+    Unique,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [Strategy::Sequential, Strategy::Random, Strategy::Unique];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::Sequential => "sequential",
+            Strategy::Random => "random",
+            Strategy::Unique => "unique",
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Strategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("Unknown feeder strategy \"{}\". Use sequential, random or unique", s))
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Rows of test data, such as user IDs from a CSV file, that templates use as
+/// `{{name.column}}`. Shared by every worker of a run.
+#[derive(Debug)]
+pub struct Feeder {
+    name: String,
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+    strategy: Strategy,
+    /// Next row for the sequential strategy
+    next: AtomicUsize,
+    /// Rows each worker has taken with the unique strategy
+    taken: Mutex<HashMap<u32, usize>>,
+}
+
+impl Feeder {
+    /// Reads a CSV file whose first line names the columns
+    pub fn from_csv(name: impl Into<String>, path: impl AsRef<Path>, strategy: Strategy) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse_csv(name, &text, strategy).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse_csv(name: impl Into<String>, text: &str, strategy: Strategy) -> Result<Self, String> {
+        let mut records = parse_csv(text).into_iter();
+        let columns: Vec<String> = records
+            .next()
+            .ok_or("the file is empty, it needs a header line naming the columns")?
+            .into_iter()
+            .map(|column| column.trim().to_string())
+            .collect();
+        let rows: Vec<Vec<String>> = records.collect();
+        if rows.is_empty() {
+            return Err("there are no rows after the header line".to_string());
+        }
+        if let Some(i) = rows.iter().position(|row| row.len() != columns.len()) {
+            return Err(format!(
+                "row {} has {} fields but the header has {} columns",
+                i + 1,
+                rows[i].len(),
+                columns.len()
+            ));
+        }
+        Ok(Self {
+            name: name.into(),
+            columns,
+            rows,
+            strategy,
+            next: AtomicUsize::new(0),
+            taken: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Index of the next row for `worker` out of `workers`
+    pub fn pick(&self, worker: u32, workers: u32) -> usize {
+        match self.strategy {
+            Strategy::Sequential => self.next.fetch_add(1, Ordering::Relaxed) % self.rows.len(),
+            Strategy::Random => rand::random_range(0..self.rows.len()),
+            Strategy::Unique => {
+                // Worker w owns rows w, w + workers, w + 2 * workers, ...
+                let (worker, workers) = (worker as usize, workers.max(1) as usize);
+                let owned = self.rows.len().saturating_sub(worker).div_ceil(workers).max(1);
+                let mut taken = self.taken.lock().unwrap();
+                let count = taken.entry(worker as u32).or_insert(0);
+                let row = worker + workers * (*count % owned);
+                *count += 1;
+                row.min(self.rows.len() - 1)
+            }
+        }
+    }
+
+    /// Value of `column` in row `row`
+    pub fn value(&self, row: usize, column: &str) -> Option<&str> {
+        let column = self.columns.iter().position(|name| name == column)?;
+        self.rows.get(row).map(|row| row[column].as_str())
+    }
+}
+
+/// Splits CSV text into records. Fields can be quoted to hold commas, line
+/// breaks or `""` for a quote. Blank lines are skipped.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|field| !field.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            c => field.push(c),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|field| !field.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "user_id,name\n1,\"Smith, Jo\"\n2,\"Say \"\"hi\"\"\"\r\n\n3,Sam\n4,Alex\n";
+
+    #[test]
+    fn csv_files_are_parsed() {
+        let feeder = Feeder::parse_csv("csv", CSV, Strategy::Sequential).unwrap();
+        assert_eq!(feeder.columns(), ["user_id", "name"]);
+        assert_eq!(feeder.len(), 4);
+        assert_eq!(feeder.value(0, "name"), Some("Smith, Jo"));
+        assert_eq!(feeder.value(1, "name"), Some("Say \"hi\""));
+        assert_eq!(feeder.value(3, "user_id"), Some("4"));
+        assert_eq!(feeder.value(0, "email"), None);
+
+        assert!(Feeder::parse_csv("csv", "", Strategy::Sequential).is_err());
+        assert!(Feeder::parse_csv("csv", "a,b\n", Strategy::Sequential).is_err());
+        let error = Feeder::parse_csv("csv", "a,b\n1,2\n3\n", Strategy::Sequential).unwrap_err();
+        assert_eq!(error, "row 2 has 1 fields but the header has 2 columns");
+    }
+
+    #[test]
+    fn rows_are_handed_out_by_strategy() {
+        let sequential = Feeder::parse_csv("csv", CSV, Strategy::Sequential).unwrap();
+        let rows: Vec<usize> = (0..6).map(|i| sequential.pick(i % 2, 2)).collect();
+        assert_eq!(rows, vec![0, 1, 2, 3, 0, 1]);
+
+        let unique = Feeder::parse_csv("csv", CSV, Strategy::Unique).unwrap();
+        let worker_1: Vec<usize> = (0..3).map(|_| unique.pick(1, 2)).collect();
+        let worker_0: Vec<usize> = (0..3).map(|_| unique.pick(0, 2)).collect();
+        assert_eq!(worker_0, vec![0, 2, 0]);
+        assert_eq!(worker_1, vec![1, 3, 1]);
+
+        let random = Feeder::parse_csv("csv", CSV, Strategy::Random).unwrap();
+        assert!((0..20).all(|_| random.pick(0, 1) < 4));
+        assert_eq!("UNIQUE".parse::<Strategy>(), Ok(Strategy::Unique));
+        assert!("shuffled".parse::<Strategy>().is_err());
+    }
+}
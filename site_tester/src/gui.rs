@@ -1,8 +1,15 @@
 use eframe::egui;
-use site_tester::{get_client, normalise_url, make_requests, get_average, Method};
+use site_tester::{
+    build_headers, normalise_url, parse_timeout, format_duration, CrawlOptions, Endpoint, ExpectedStatus,
+    Arrival, Event, LoadTest, LoadTestConfig, Method, Progress, Rate, RunLength, SampleWriter, Scenario, Stage,
+    Stages, Subscriber, Threshold,
+};
+use site_tester::feeder::Strategy;
+use site_tester::scenario::{FeederFile, FollowLinks, Load, Step as ScenarioStep, Target, WeightedUrl};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
 
 const ASCII_BANNER: &str = r#"
      _____ _____ _______ ______ _______ ______  _____ _______ ______ _____     
@@ -18,17 +25,55 @@ const LOGS_MAX_CAPACITY: usize = 100000;
 pub struct SiteTesterApp {
     url: String,
     force: bool,
-    number: u32,
+    /// URL and weight of each endpoint of a traffic mix, sent to in place of `url`
+    endpoints: Vec<(String, u32)>,
+    number: u64,
+    run_for_duration: bool,
+    duration: f64,
+    use_rate: bool,
+    rate: Rate,
+    use_stages: bool,
+    /// Stage durations in seconds and their targets
+    stages: Vec<(f64, f64)>,
     processes: u32,
     ignore_ssl: bool,
-    timeout: f32,
+    timeout: f64,
     verbose: bool,
-    times: Arc<Mutex<Vec<u32>>>,
+    test: Option<LoadTest>,
+    progress: Option<Progress>,
     running: bool,
     message: String,
-    cancel_flag: Arc<AtomicBool>,
-    logs: Arc<Mutex<VecDeque<String>>>,
+    histogram: Vec<String>,
+    status_lines: Vec<String>,
+    samples_path: String,
+    /// Streams rows to `samples_file()` while a run is going
+    samples: Option<Arc<SampleWriter>>,
+    /// Whether `samples_file()` holds every row of the last finished run
+    samples_ready: bool,
+    export_message: String,
+    logs: Arc<Mutex<VecDeque<Event>>>,
+    errors_only: bool,
     method: Method,
+    custom_method: String,
+    payload: String,
+    headers: Vec<(String, String)>,
+    bearer: String,
+    basic_auth: String,
+    expected_status: String,
+    follow_links: bool,
+    crawl_options: CrawlOptions,
+    /// Comma-separated threshold expressions
+    thresholds: String,
+    /// Report outputs of a loaded scenario, kept so saving does not drop them
+    outputs: Vec<String>,
+    /// Journey steps of a loaded scenario, sent in place of the method and payload
+    steps: Vec<ScenarioStep>,
+    /// Name, CSV path and strategy of each data feeder
+    feeders: Vec<(String, String, Strategy)>,
+    scenario_path: String,
+    /// Directory of the loaded scenario, which its relative data paths are taken from
+    scenario_dir: Option<PathBuf>,
+    scenario_message: String,
 }
 
 impl Default for SiteTesterApp {
@@ -36,17 +81,46 @@ impl Default for SiteTesterApp {
         Self {
             url: String::from("https://"),
             force: false,
+            endpoints: Vec::new(),
             number: 100,
+            run_for_duration: false,
+            duration: 60.0,
+            use_rate: false,
+            rate: Rate { per_second: 10.0, arrival: Arrival::Constant },
+            use_stages: false,
+            stages: vec![(30.0, 10.0), (60.0, 10.0), (30.0, 0.0)],
             processes: 10,
             ignore_ssl: false,
             timeout: 10.0,
             verbose: false,
-            times: Arc::new(Mutex::new(Vec::new())),
+            test: None,
+            progress: None,
             running: false,
             message: String::new(),
-            cancel_flag: Arc::new(AtomicBool::new(false)),
+            histogram: Vec::new(),
+            status_lines: Vec::new(),
+            samples_path: String::from("samples.csv"),
+            samples: None,
+            samples_ready: false,
+            export_message: String::new(),
             logs: Arc::new(Mutex::new(VecDeque::with_capacity(LOGS_MAX_CAPACITY))),
+            errors_only: false,
             method: Method::Get,
+            custom_method: String::new(),
+            payload: String::new(),
+            headers: Vec::new(),
+            bearer: String::new(),
+            basic_auth: String::new(),
+            expected_status: ExpectedStatus::default().to_string(),
+            follow_links: false,
+            crawl_options: CrawlOptions::default(),
+            thresholds: String::new(),
+            outputs: Vec::new(),
+            steps: Vec::new(),
+            feeders: Vec::new(),
+            scenario_path: String::from("scenario.toml"),
+            scenario_dir: None,
+            scenario_message: String::new(),
         }
     }
 }
@@ -55,18 +129,98 @@ impl eframe::App for SiteTesterApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.label(egui::RichText::new(ASCII_BANNER).monospace().strong());
+            ui.horizontal(|ui| {
+                ui.label("Scenario file (.toml, .yaml):");
+                ui.text_edit_singleline(&mut self.scenario_path);
+                if ui.button("Load scenario").clicked() {
+                    let loaded = Scenario::load(&self.scenario_path).map(|(scenario, config)| self.apply_scenario(&scenario, config));
+                    self.scenario_message = match loaded {
+                        Ok(()) => format!("Loaded {}", self.scenario_path),
+                        Err(e) => format!("Load failed: {}", e),
+                    };
+                }
+                if ui.button("Save scenario").clicked() {
+                    self.scenario_message = match self.to_scenario().save(&self.scenario_path) {
+                        Ok(()) => format!("Saved {}", self.scenario_path),
+                        Err(e) => format!("Save failed: {}", e),
+                    };
+                }
+                ui.label(&self.scenario_message);
+            });
             ui.horizontal(|ui| {
                 ui.label("URL:");
                 ui.text_edit_singleline(&mut self.url);
                 ui.checkbox(&mut self.force, "Force URL");
             });
+            egui::CollapsingHeader::new("Traffic mix").show(ui, |ui| {
+                ui.label("Each request goes to one of these URLs, picked by weight, in place of the URL above");
+                let mut remove = None;
+                for (i, (url, weight)) in self.endpoints.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("URL:");
+                        ui.text_edit_singleline(url);
+                        ui.label("Weight:");
+                        ui.add(egui::DragValue::new(weight).clamp_range(1..=u32::MAX));
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.endpoints.remove(i);
+                }
+                if ui.button("Add endpoint").clicked() {
+                    self.endpoints.push((self.url.clone(), 1));
+                }
+            });
             ui.horizontal(|ui| {
-                ui.label("Number of requests:");
-                ui.add(egui::DragValue::new(&mut self.number));
+                ui.radio_value(&mut self.run_for_duration, false, "Number of requests:");
+                ui.add_enabled(!self.run_for_duration, egui::DragValue::new(&mut self.number));
+                ui.radio_value(&mut self.run_for_duration, true, "Duration (seconds):");
+                ui.add_enabled(
+                    self.run_for_duration,
+                    egui::DragValue::new(&mut self.duration).clamp_range(0.0..=f64::MAX)
+                );
             });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.use_rate, "Fixed rate (requests/s):");
+                ui.add_enabled(
+                    self.use_rate,
+                    egui::DragValue::new(&mut self.rate.per_second).clamp_range(0.001..=f64::MAX)
+                );
+                ui.add_enabled_ui(self.use_rate, |ui| {
+                    egui::ComboBox::from_id_source("arrival_combo")
+                        .selected_text(self.rate.arrival.to_string())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.rate.arrival, Arrival::Constant, "constant");
+                            ui.selectable_value(&mut self.rate.arrival, Arrival::Poisson, "poisson");
+                        });
+                });
+            });
+            ui.checkbox(&mut self.use_stages, "Staged load profile (targets are workers, or requests/s with a fixed rate)");
+            if self.use_stages {
+                let mut remove = None;
+                for (i, (duration, target)) in self.stages.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Stage {}: ramp over (seconds):", i + 1));
+                        ui.add(egui::DragValue::new(duration).clamp_range(0.001..=f64::MAX));
+                        ui.label("to target:");
+                        ui.add(egui::DragValue::new(target).clamp_range(0.0..=f64::MAX));
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.stages.remove(i);
+                }
+                if ui.button("Add stage").clicked() {
+                    self.stages.push((30.0, self.stages.last().map_or(10.0, |&(_, target)| target)));
+                }
+            }
             ui.horizontal(|ui| {
                 ui.label("Concurrent processes:");
-                ui.add(egui::DragValue::new(&mut self.processes));
+                ui.add(egui::DragValue::new(&mut self.processes).clamp_range(1..=u32::MAX));
             });
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.ignore_ssl, "Ignore SSL");
@@ -74,21 +228,127 @@ impl eframe::App for SiteTesterApp {
                 ui.label("Timeout (seconds):");
                 ui.add(egui::DragValue::new(&mut self.timeout));
                 ui.label("Method:");
+                let is_custom = matches!(self.method, Method::Custom(_));
                 egui::ComboBox::from_id_source("method_combo")
-                    .selected_text(match self.method {
-                        Method::Get => "GET",
-                        Method::Post => "POST",
-                    })
+                    .selected_text(if is_custom { "CUSTOM" } else { self.method.as_str() })
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(&mut self.method, Method::Get, "GET");
-                        ui.selectable_value(&mut self.method, Method::Post, "POST");
+                        for method in Method::STANDARD {
+                            let label = method.as_str().to_string();
+                            ui.selectable_value(&mut self.method, method, label);
+                        }
+                        if ui.selectable_label(is_custom, "CUSTOM").clicked() {
+                            self.method = Method::Custom(self.custom_method.clone());
+                        }
                     });
+                if is_custom {
+                    ui.label("Verb:");
+                    ui.text_edit_singleline(&mut self.custom_method);
+                }
+            });
+            if self.method.has_body() && self.steps.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.label("Payload (JSON or file path):");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.payload)
+                            .code_editor()
+                            .desired_rows(3)
+                    );
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Expected status:");
+                ui.text_edit_singleline(&mut self.expected_status);
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.follow_links, "Follow links");
+                if self.follow_links {
+                    ui.label("Max depth:");
+                    ui.add(egui::DragValue::new(&mut self.crawl_options.max_depth));
+                    ui.label("Max pages:");
+                    ui.add(egui::DragValue::new(&mut self.crawl_options.max_pages));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Thresholds (e.g. p95<300ms, error_rate<1%):");
+                ui.text_edit_singleline(&mut self.thresholds);
+            });
+            if !self.steps.is_empty() {
+                ui.horizontal(|ui| {
+                    let names: Vec<&str> = self.steps.iter().map(|step| step.name.as_str()).collect();
+                    ui.label(format!("Journey steps from the scenario: {}", names.join(" -> ")));
+                    if ui.button("Clear steps").clicked() {
+                        self.steps.clear();
+                    }
+                });
+            }
+            if !self.outputs.is_empty() {
+                ui.label(format!("Report outputs kept from the scenario: {}", self.outputs.join(", ")));
+            }
+            egui::CollapsingHeader::new("Headers & Authentication").show(ui, |ui| {
+                let mut remove = None;
+                for (i, (name, value)) in self.headers.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.text_edit_singleline(name);
+                        ui.label("Value:");
+                        ui.text_edit_singleline(value);
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.headers.remove(i);
+                }
+                if ui.button("Add header").clicked() {
+                    self.headers.push((String::new(), String::new()));
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Bearer token:");
+                    ui.add(egui::TextEdit::singleline(&mut self.bearer).password(true));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Basic auth (user:password):");
+                    ui.add(egui::TextEdit::singleline(&mut self.basic_auth).password(true));
+                });
+            });
+            egui::CollapsingHeader::new("Templates & Data feeders").show(ui, |ui| {
+                ui.label(
+                    "The URL, header values and payload can use {{uuid}}, {{seq}}, {{randInt 1 1000}}, \
+                    {{timestamp}}, {{now}} and a column of a feeder's CSV file as {{name.column}}",
+                );
+                let mut remove = None;
+                for (i, (name, path, strategy)) in self.feeders.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("Name:");
+                        ui.add(egui::TextEdit::singleline(name).desired_width(80.0));
+                        ui.label("CSV file:");
+                        ui.text_edit_singleline(path);
+                        egui::ComboBox::from_id_source(("feeder_strategy_combo", i))
+                            .selected_text(strategy.as_str())
+                            .show_ui(ui, |ui| {
+                                for option in Strategy::ALL {
+                                    ui.selectable_value(strategy, option, option.as_str());
+                                }
+                            });
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.feeders.remove(i);
+                }
+                if ui.button("Add feeder").clicked() {
+                    let name = if self.feeders.is_empty() { "csv".to_string() } else { format!("csv{}", self.feeders.len() + 1) };
+                    self.feeders.push((name, String::new(), Strategy::default()));
+                }
             });
 
             ui.add_space(8.0);
             if !self.message.is_empty() {
                 let lines: Vec<&str> = self.message.split('\n').collect();
-                for (i, line) in lines.iter().enumerate() {
+                for line in lines.iter() {
                     if line.contains("Completed a total") {
                         ui.label(
                             egui::RichText::new(*line)
@@ -110,6 +370,13 @@ impl eframe::App for SiteTesterApp {
                                 .color(egui::Color32::LIGHT_YELLOW)
                                 .strong()
                         );
+                    } else if line.contains("Could not sustain") {
+                        ui.label(
+                            egui::RichText::new(*line)
+                                .size(20.0)
+                                .color(egui::Color32::from_rgb(255, 95, 0))
+                                .strong()
+                        );
                     } else if line.contains("failed") || line.contains("Failed") {
                         ui.label(
                             egui::RichText::new(*line)
@@ -126,122 +393,508 @@ impl eframe::App for SiteTesterApp {
                     }
                 }
             }
+            if !self.status_lines.is_empty() {
+                ui.collapsing("Status codes", |ui| {
+                    for line in &self.status_lines {
+                        ui.label(egui::RichText::new(line).monospace());
+                    }
+                });
+            }
+            if !self.histogram.is_empty() {
+                ui.collapsing("Latency histogram", |ui| {
+                    for line in &self.histogram {
+                        ui.label(egui::RichText::new(line).monospace());
+                    }
+                });
+            }
+            if self.samples_ready {
+                ui.horizontal(|ui| {
+                    ui.label("Samples CSV:");
+                    ui.text_edit_singleline(&mut self.samples_path);
+                    if ui.button("Export samples").clicked() {
+                        self.export_message = match std::fs::copy(samples_file(), &self.samples_path) {
+                            Ok(_) => format!("Exported samples to {}", self.samples_path),
+                            Err(e) => format!("Export failed: {}", e),
+                        };
+                    }
+                });
+            }
+            if !self.export_message.is_empty() {
+                ui.label(&self.export_message);
+            }
             ui.add_space(8.0);
 
-            // // Debug info section
-            // ui.separator();
-            // ui.heading("Debug Info");
-            // ui.label(format!("URL: {}", self.url));
-            // ui.label(format!("Force URL: {}", self.force));
-            // ui.label(format!("Number of requests: {}", self.number));
-            // ui.label(format!("Processes: {}", self.processes));
-            // ui.label(format!("Ignore SSL: {}", self.ignore_ssl));
-            // ui.label(format!("Timeout (seconds): {}", self.timeout));
-            // ui.label(format!("Verbose: {}", self.verbose));
-            // ui.label(format!("Running: {}", self.running));
-            // {
-            //     let t = self.times.lock().unwrap();
-            //     ui.label(format!("Times vector length: {}", t.len()));
-            //     ui.label(format!("Completed requests: {}", t.iter().filter(|&&x| x != 0).count()));
-            //     // Show first few times for inspection
-            //     ui.label(format!("First 5 times: {:?}", t.iter().take(5).collect::<Vec<_>>()));
-            // }
-
             if ui.button("Start").clicked() {
-                self.cancel_flag.store(true, Ordering::SeqCst);
+                if let Some(test) = self.test.take() {
+                    test.cancel();
+                }
 
-                // Reset state for new run
-                self.times = Arc::new(Mutex::new(vec![0; self.number as usize]));
-                self.cancel_flag = Arc::new(AtomicBool::new(false));
-                self.running = true; // <-- Ensure running is set to true on start
+                if matches!(self.method, Method::Custom(_)) {
+                    match self.custom_method.to_uppercase().parse::<Method>() {
+                        Ok(method) => self.method = method,
+                        Err(e) => {
+                            self.message = format!("Failed to start: {}", e);
+                            self.running = false;
+                            return;
+                        }
+                    }
+                }
+                let scenario = Scenario {
+                    steps: self.steps.clone(),
+                    feeders: self.feeder_files(),
+                    base_dir: self.scenario_dir.clone(),
+                    ..Scenario::default()
+                };
+                let payload = match scenario.resolve_payload(&self.payload) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                };
+                let header_lines: Vec<String> = self
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| !name.trim().is_empty())
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                let headers = match build_headers(
+                    header_lines.iter().map(String::as_str),
+                    Some(self.bearer.as_str()).filter(|s| !s.is_empty()),
+                    Some(self.basic_auth.as_str()).filter(|s| !s.is_empty()),
+                ) {
+                    Ok(headers) => headers,
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                };
+                let expected_status = match self.expected_status.parse::<ExpectedStatus>() {
+                    Ok(expected_status) => expected_status,
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                };
+                self.message.clear();
+                self.histogram.clear();
+                self.status_lines.clear();
+                self.export_message.clear();
 
                 {
                     let mut logs_guard = self.logs.lock().unwrap();
                     logs_guard.clear();
                 }
                 self.url = normalise_url(self.url.clone(), self.force);
-                let url = Arc::new(self.url.clone());
-                let timeout: u16 = (self.timeout * 1000.0) as u16;
-
-                let client = get_client(timeout, self.ignore_ssl);
-                let number = self.number;
-                let processes = self.processes;
-                let verbose = self.verbose;
-                let logs = Arc::clone(&self.logs);
+                let timeout = match parse_timeout(self.timeout) {
+                    Ok(timeout) => timeout,
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                };
 
-                let ok_closure = {
-                    let logs = Arc::clone(&logs);
-                    move |msg: String| {
-                        let mut logs = logs.lock().unwrap();
-                        if logs.len() == LOGS_MAX_CAPACITY {
-                            logs.pop_front();
+                let mut config = LoadTestConfig::new(self.url.clone())
+                    .workers(self.processes)
+                    .method(self.method.clone())
+                    .headers(headers)
+                    .expected_status(expected_status)
+                    .timeout(timeout)
+                    .ignore_ssl(self.ignore_ssl);
+                config = if self.run_for_duration {
+                    match Duration::try_from_secs_f64(self.duration) {
+                        Ok(duration) => config.duration(duration),
+                        Err(_) => {
+                            self.message = format!("Failed to start: invalid duration {}", self.duration);
+                            self.running = false;
+                            return;
                         }
-                        logs.push_back(format!("OUTPUT: {}", msg));
                     }
+                } else {
+                    config.requests(self.number)
                 };
-                let err_closure = {
-                    let logs = Arc::clone(&logs);
-                    move |msg: String| {
-                        let mut logs = logs.lock().unwrap();
-                        if logs.len() == LOGS_MAX_CAPACITY {
-                            logs.pop_front();
+                if self.use_rate {
+                    config = config.rate(self.rate);
+                }
+                if self.use_stages {
+                    let mut parsed = Vec::new();
+                    for &(seconds, target) in &self.stages {
+                        match Duration::try_from_secs_f64(seconds) {
+                            Ok(duration) if !duration.is_zero() => parsed.push(Stage { duration, target }),
+                            _ => {
+                                self.message = format!("Failed to start: invalid stage duration {}", seconds);
+                                self.running = false;
+                                return;
+                            }
                         }
-                        logs.push_back(format!("ERROR: {}", msg));
                     }
-                };
+                    if parsed.is_empty() {
+                        self.message = "Failed to start: at least one stage is required".to_string();
+                        self.running = false;
+                        return;
+                    }
+                    config = config.stages(Stages(parsed));
+                }
+                // Processes are raised when fewer could not keep up with the rate
+                config.profile.fit_workers_to_rate();
+                if let Some(payload) = payload {
+                    config = config.payload(payload);
+                }
+                if self.follow_links {
+                    config = config.follow_links(self.crawl_options);
+                }
+                for (url, weight) in &self.endpoints {
+                    config = config.endpoint(Endpoint::new(normalise_url(url.trim().to_string(), self.force), *weight));
+                }
+                match scenario.journey_steps() {
+                    Ok(steps) => config.steps = steps,
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                }
+                match scenario.data_feeders() {
+                    Ok(feeders) => {
+                        for feeder in feeders {
+                            config = config.feeder(feeder);
+                        }
+                    }
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                }
+                for expression in self.thresholds.split(',').filter(|s| !s.trim().is_empty()) {
+                    match expression.parse::<Threshold>() {
+                        Ok(threshold) => config = config.threshold(threshold),
+                        Err(e) => {
+                            self.message = format!("Failed to start: {}", e);
+                            self.running = false;
+                            return;
+                        }
+                    }
+                }
 
-                make_requests(
-                    url,
-                    number,
-                    processes,
-                    client,
-                    verbose,
-                    (ok_closure, err_closure),
-                    Arc::clone(&self.times),
-                    Arc::clone(&self.cancel_flag),
-                    self.method,
-                );
+                let logs = Arc::clone(&self.logs);
+                let verbose = self.verbose;
+                config = config.subscribe(move |event: &Event| match event {
+                    Event::RequestFailed { .. } => push_log(&logs, event.clone()),
+                    Event::RequestCompleted { .. } if verbose => push_log(&logs, event.clone()),
+                    _ => {}
+                });
+                // Rows go to a scratch file during the run, ready to export once it finishes
+                self.samples_ready = false;
+                match SampleWriter::create(samples_file()) {
+                    Ok(samples) => {
+                        let samples = Arc::new(samples);
+                        let writer = Arc::clone(&samples);
+                        config = config.subscribe(move |event: &Event| writer.on_event(event));
+                        self.samples = Some(samples);
+                    }
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                }
+
+                match LoadTest::start(config) {
+                    Ok(test) => {
+                        self.test = Some(test);
+                        self.running = true;
+                    }
+                    Err(e) => {
+                        self.message = format!("Failed to start: {}", e);
+                        self.running = false;
+                        return;
+                    }
+                }
             }
 
-            let times_len = {
-                let t = self.times.lock().unwrap();
-                t.iter().filter(|&&x| x != 0).count()
-            };
-            ui.label(format!("{}/{}", times_len, self.number));
+            if let Some(test) = &mut self.test {
+                self.progress = Some(test.progress());
+            }
+            if let Some(progress) = self.progress {
+                match progress.length {
+                    RunLength::Requests(number) => {
+                        ui.label(format!("{}/{}", progress.completed, number));
+                    }
+                    RunLength::Duration(duration) => {
+                        ui.label(format!(
+                            "{}/{} - {} requests",
+                            format_duration(Duration::from_secs(progress.elapsed.min(duration).as_secs())),
+                            format_duration(duration),
+                            progress.completed
+                        ));
+                    }
+                }
+            }
 
             if self.running {
                 ctx.request_repaint();
             }
 
-            // Fix: Only set self.message and self.running when all requests are done
-            if self.running && times_len >= self.number as usize {
-                let times = self.times.lock().unwrap();
-                let (average_value, fails, max_time) = get_average(&times);
-                self.message = format!(
-                    "Completed a total of {} requests \n\
-                    Average time of successful requests: {:?} \n\
-                    Maximum time of successful request: {:?} \n\
-                    Total failed requests: {}",
-                    self.number, average_value, max_time, fails
-                );
+            let finished = self.progress.is_some_and(|progress| progress.finished);
+            if self.running && finished {
+                let report = self.test.take().expect("A running test").wait();
+                let stats = &report.stats;
+                self.message = format!("Completed a total of {} requests", stats.total());
+                for (label, value) in stats.summary() {
+                    self.message.push_str(&format!("\n{}: {}", label, value));
+                }
+                if let Some(warning) = report.rate_warning() {
+                    self.message.push_str(&format!("\n{}", warning));
+                }
+                let unit = if report.config.profile.rate.is_some() { " requests/s" } else { " workers" };
+                for (i, (stage, stage_stats)) in report.per_stage().into_iter().enumerate() {
+                    self.message.push_str(&format!(
+                        "\nStage {} ({} to {}{}): {} requests, average {:?}, p95 {:?}, {} errors",
+                        i + 1,
+                        format_duration(stage.duration),
+                        stage.target,
+                        unit,
+                        stage_stats.total(),
+                        stage_stats.mean(),
+                        stage_stats.percentile(95.0),
+                        stage_stats.fails()
+                    ));
+                }
+                for result in report.check_thresholds() {
+                    self.message.push_str(&format!("\nThreshold {}", result));
+                }
+                for (endpoint, endpoint_stats) in report.per_endpoint() {
+                    self.message.push_str(&format!(
+                        "\n{} (weight {}): {} requests, average {:?}, p95 {:?}, {} errors",
+                        endpoint.url,
+                        endpoint.weight,
+                        endpoint_stats.total(),
+                        endpoint_stats.mean(),
+                        endpoint_stats.percentile(95.0),
+                        endpoint_stats.fails()
+                    ));
+                }
+                for (name, step_stats) in report.per_step() {
+                    self.message.push_str(&format!(
+                        "\nStep {}: {} requests, average {:?}, p95 {:?}, {} errors",
+                        name,
+                        step_stats.total(),
+                        step_stats.mean(),
+                        step_stats.percentile(95.0),
+                        step_stats.fails()
+                    ));
+                }
+                self.histogram = stats.histogram_lines(10, 40);
+                self.status_lines = stats.status_lines();
+                if report.crawled() {
+                    let pages = report.per_url();
+                    self.message.push_str(&format!("\nVisited {} unique pages", pages.len()));
+                    for (page, page_stats) in pages {
+                        self.message.push_str(&format!(
+                            "\n{}: {} requests, average {:?}, p95 {:?}, max {:?}, {} errors",
+                            page,
+                            page_stats.total(),
+                            page_stats.mean(),
+                            page_stats.percentile(95.0),
+                            page_stats.max(),
+                            page_stats.fails()
+                        ));
+                    }
+                }
+                if let Some(samples) = self.samples.take() {
+                    match samples.finish() {
+                        Ok(()) => self.samples_ready = true,
+                        Err(e) => self.export_message = e,
+                    }
+                }
                 self.running = false;
             }
 
             ui.separator();
-            ui.heading("Logs");
+            ui.horizontal(|ui| {
+                ui.heading("Logs");
+                ui.checkbox(&mut self.errors_only, "Errors only");
+            });
             egui::ScrollArea::vertical()
                 .auto_shrink([false; 2])
                 .stick_to_bottom(true)
                 .show(ui, |ui| {
                     let logs_guard = self.logs.lock().unwrap();
-                    for log in logs_guard.iter() {
-                        ui.label(log);
+                    for event in logs_guard.iter() {
+                        match event {
+                            Event::RequestFailed { .. } => {
+                                ui.colored_label(egui::Color32::LIGHT_RED, event.to_string());
+                            }
+                            _ if !self.errors_only => {
+                                ui.label(event.to_string());
+                            }
+                            _ => {}
+                        }
                     }
                 });
         });
     }
 }
 
+impl SiteTesterApp {
+    /// The current settings as a scenario that can be saved
+    fn to_scenario(&self) -> Scenario {
+        let journey = !self.steps.is_empty();
+        let target = Target {
+            url: if self.endpoints.is_empty() { self.url.clone() } else { String::new() },
+            endpoints: self
+                .endpoints
+                .iter()
+                .map(|(url, weight)| WeightedUrl {
+                    url: url.trim().to_string(),
+                    weight: Some(*weight),
+                })
+                .collect(),
+            force_url: self.force,
+            method: Some(match &self.method {
+                Method::Custom(_) => self.custom_method.to_uppercase(),
+                method => method.as_str().to_string(),
+            })
+            .filter(|_| !journey),
+            headers: self
+                .headers
+                .iter()
+                .filter(|(name, _)| !name.trim().is_empty())
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .collect(),
+            bearer: Some(self.bearer.clone()).filter(|s| !s.is_empty()),
+            basic_auth: Some(self.basic_auth.clone()).filter(|s| !s.is_empty()),
+            payload: Some(self.payload.clone()).filter(|s| !journey && self.method.has_body() && !s.trim().is_empty()),
+            expect_status: Some(self.expected_status.clone()),
+            timeout: Some(format!("{}s", self.timeout)),
+            ignore_ssl: self.ignore_ssl,
+        };
+        let mut load = Load {
+            workers: Some(self.processes),
+            ..Load::default()
+        };
+        if self.use_stages {
+            let stages: Vec<String> = self.stages.iter().map(|(seconds, target)| format!("{}s:{}", seconds, target)).collect();
+            load.stages = Some(stages.join(","));
+        } else if self.run_for_duration {
+            load.duration = Some(format!("{}s", self.duration));
+        } else {
+            load.requests = Some(self.number);
+        }
+        if self.use_rate {
+            load.rate = Some(format!("{}/s", self.rate.per_second));
+            load.arrival = Some(self.rate.arrival.to_string());
+        }
+        Scenario {
+            thresholds: self
+                .thresholds
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            outputs: self.outputs.clone(),
+            target,
+            load,
+            follow_links: self.follow_links.then_some(FollowLinks {
+                max_depth: self.crawl_options.max_depth,
+                max_pages: self.crawl_options.max_pages,
+            }),
+            steps: self.steps.clone(),
+            feeders: self.feeder_files(),
+            base_dir: self.scenario_dir.clone(),
+        }
+    }
+
+    fn feeder_files(&self) -> Vec<FeederFile> {
+        self.feeders
+            .iter()
+            .map(|(name, path, strategy)| FeederFile {
+                name: name.trim().to_string(),
+                path: path.trim().to_string(),
+                strategy: Some(strategy.to_string()).filter(|_| *strategy != Strategy::default()),
+            })
+            .collect()
+    }
+
+    /// Fills in the settings from a loaded scenario and the test built from it
+    fn apply_scenario(&mut self, scenario: &Scenario, config: LoadTestConfig) {
+        let target = &scenario.target;
+        self.scenario_dir = scenario.base_dir.clone();
+
+        self.url = if target.url.is_empty() { config.url.clone() } else { target.url.clone() };
+        self.endpoints = target
+            .endpoints
+            .iter()
+            .map(|endpoint| (endpoint.url.clone(), endpoint.weight.unwrap_or(1)))
+            .collect();
+        self.force = target.force_url;
+        if let Method::Custom(verb) = &config.method {
+            self.custom_method = verb.clone();
+        }
+        self.method = config.method.clone();
+        self.headers = target.headers.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        self.bearer = target.bearer.clone().unwrap_or_default();
+        self.basic_auth = target.basic_auth.clone().unwrap_or_default();
+        self.payload = target.payload.clone().unwrap_or_default();
+        self.expected_status = config.expected_status.to_string();
+        self.timeout = config.timeout.as_secs_f64();
+        self.ignore_ssl = config.ignore_ssl;
+
+        let profile = &config.profile;
+        match profile.length {
+            RunLength::Requests(number) => {
+                self.run_for_duration = false;
+                self.number = number;
+            }
+            RunLength::Duration(duration) => {
+                self.run_for_duration = true;
+                self.duration = duration.as_secs_f64();
+            }
+        }
+        self.processes = profile.workers;
+        self.use_rate = profile.rate.is_some();
+        if let Some(rate) = profile.rate {
+            self.rate = rate;
+        }
+        self.use_stages = profile.stages.is_some();
+        if let Some(stages) = &profile.stages {
+            self.stages = stages.0.iter().map(|stage| (stage.duration.as_secs_f64(), stage.target)).collect();
+        }
+        self.follow_links = config.crawl.is_some();
+        if let Some(options) = config.crawl {
+            self.crawl_options = options;
+        }
+        self.thresholds = scenario.thresholds.join(", ");
+        self.outputs = scenario.outputs.clone();
+        self.steps = scenario.steps.clone();
+        self.feeders = config
+            .feeders
+            .iter()
+            .zip(&scenario.feeders)
+            .map(|(feeder, file)| (feeder.name().to_string(), file.path.clone(), feeder.strategy()))
+            .collect();
+    }
+}
+
+/// Scratch CSV the rows of the current run are streamed to
+fn samples_file() -> PathBuf {
+    std::env::temp_dir().join(format!("site_tester_samples_{}.csv", std::process::id()))
+}
+
+fn push_log(logs: &Mutex<VecDeque<Event>>, event: Event) {
+    let mut logs = logs.lock().unwrap();
+    if logs.len() == LOGS_MAX_CAPACITY {
+        logs.pop_front();
+    }
+    logs.push_back(event);
+}
+
 pub fn run_gui() -> eframe::Result<()> {
     let options = eframe::NativeOptions::default();
     eframe::run_native(
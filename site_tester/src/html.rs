@@ -0,0 +1,333 @@
+use crate::load_test::Report;
+use crate::output::{format_timestamp, json_report};
+use crate::stats::SecondStats;
+use serde_json::Value;
+use std::fmt::Write;
+
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 240.0;
+/// Room around the plot for axis labels
+const MARGIN: f64 = 48.0;
+const BLUE: &str = "#3b82f6";
+const ORANGE: &str = "#f97316";
+const RED: &str = "#ef4444";
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em auto;max-width:800px;color:#222}\
+h1{font-size:1.5em}h2{font-size:1.15em;margin-top:2em}\
+table{border-collapse:collapse}td,th{padding:2px 12px 2px 0;text-align:left}\
+th{font-weight:600}svg{display:block}svg text{font-size:11px;fill:#555}\
+.legend span{margin-right:1em}.warning{color:#b45309}";
+
+/// One offline HTML page with the run's config, summary and charts drawn as
+/// inline SVG, so it can be shared without any other files.
+pub(crate) fn render(report: &Report) -> String {
+    let stats = &report.stats;
+    let per_second = report.per_second();
+    let mut html = String::new();
+
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Site-Tester report for {url}</title>\
+        <style>{STYLE}</style></head><body>\n<h1>Site-Tester report for {url}</h1>\n<p>{started} to {finished}{cancelled}</p>\n",
+        url = escape(&report.config.url),
+        started = format_timestamp(report.started_at),
+        finished = format_timestamp(report.finished_at()),
+        cancelled = if report.cancelled { " (cancelled)" } else { "" },
+    );
+    if let Some(warning) = report.rate_warning() {
+        let _ = writeln!(html, "<p class=\"warning\">{}</p>", escape(&warning));
+    }
+
+    html.push_str("<h2>Summary</h2>\n");
+    let summary: Vec<(String, String)> = stats.summary();
+    html.push_str(&table(summary.iter().map(|(label, value)| (label.clone(), value.clone()))));
+
+    if stats.successes() > 0 {
+        html.push_str("<h2>Latency over time</h2>\n");
+        // Seconds without a successful request have no latency to plot
+        let latency = |value: fn(&SecondStats) -> std::time::Duration| -> Vec<f64> {
+            let point = |second: &SecondStats| if second.successes() > 0 { millis(value(second)) } else { f64::NAN };
+            per_second.iter().map(point).collect()
+        };
+        let mean = latency(SecondStats::mean);
+        let p95 = latency(|second| second.percentile(95.0));
+        html.push_str(&line_chart(&[("Average", BLUE, &mean), ("p95", ORANGE, &p95)], "ms"));
+    }
+
+    html.push_str("<h2>Throughput over time</h2>\n");
+    let requests: Vec<f64> = per_second.iter().map(|second| second.total() as f64).collect();
+    let failures: Vec<f64> = per_second.iter().map(|second| second.fails() as f64).collect();
+    html.push_str(&line_chart(&[("Requests/s", BLUE, &requests), ("Failures/s", RED, &failures)], "req/s"));
+
+    if stats.successes() > 0 {
+        html.push_str("<h2>Latency histogram</h2>\n");
+        let buckets: Vec<(String, f64)> = stats
+            .buckets(20)
+            .into_iter()
+            .map(|(start, end, count)| (format!("{:?} - {:?}", start, end), count as f64))
+            .collect();
+        html.push_str(&bar_chart(&buckets, BLUE));
+    }
+
+    if !stats.status_codes().is_empty() {
+        html.push_str("<h2>Status codes</h2>\n");
+        let codes: Vec<(String, f64)> = stats
+            .status_codes()
+            .iter()
+            .map(|(status, count)| (status.to_string(), *count as f64))
+            .collect();
+        html.push_str(&bar_chart(&codes, BLUE));
+        html.push_str(&table(stats.status_lines().into_iter().filter_map(|line| {
+            line.split_once(": ").map(|(status, count)| (status.to_string(), count.to_string()))
+        })));
+    }
+    let thresholds = report.check_thresholds();
+    if !thresholds.is_empty() {
+        html.push_str("<h2>Thresholds</h2>\n");
+        html.push_str(&table(thresholds.iter().map(|result| {
+            let verdict = if result.passed { "passed" } else { "FAILED" };
+            let actual = result.actual.as_deref().unwrap_or("nothing to measure");
+            (result.threshold.to_string(), format!("{} (actual {})", verdict, actual))
+        })));
+    }
+    if !stats.errors().is_empty() {
+        html.push_str("<h2>Errors</h2>\n");
+        html.push_str(&table(stats.errors().iter().map(|(kind, count)| (kind.to_string(), count.to_string()))));
+    }
+
+    html.push_str("<h2>Configuration</h2>\n");
+    let json = json_report(report);
+    let mut config = vec![("url".to_string(), report.config.url.clone())];
+    if let Some(fields) = json["config"].as_object() {
+        config.extend(fields.iter().map(|(name, value)| (name.clone(), config_value(value))));
+    }
+    html.push_str(&table(config.into_iter()));
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+fn config_value(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) if items.is_empty() => "-".to_string(),
+        Value::Array(items) => items.iter().map(config_value).collect::<Vec<_>>().join(", "),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(name, value)| format!("{} {}", name, config_value(value)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn table(rows: impl Iterator<Item = (String, String)>) -> String {
+    let mut html = String::from("<table>\n");
+    for (label, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", escape(&label), escape(&value));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Lines over the seconds of the run, one per series of (name, colour, values).
+/// `NaN` values are seconds with nothing to plot and leave a gap in the line.
+fn line_chart(series: &[(&str, &str, &[f64])], unit: &str) -> String {
+    let seconds = series.iter().map(|(_, _, values)| values.len()).max().unwrap_or(0);
+    let top = nice_max(series.iter().flat_map(|(_, _, values)| values.iter().copied()));
+    let x = |second: usize| MARGIN + second as f64 * (WIDTH - 2.0 * MARGIN) / (seconds.max(2) - 1) as f64;
+    let y = |value: f64| HEIGHT - MARGIN / 2.0 - value / top * (HEIGHT - MARGIN);
+
+    let mut svg = svg_start();
+    svg.push_str(&axes(top, unit));
+    let _ = write!(
+        svg,
+        "<text x=\"{:.0}\" y=\"{:.0}\">0s</text><text x=\"{:.0}\" y=\"{:.0}\" text-anchor=\"end\">{}s</text>",
+        MARGIN,
+        HEIGHT - 4.0,
+        WIDTH - MARGIN,
+        HEIGHT - 4.0,
+        seconds.saturating_sub(1)
+    );
+    for (_, colour, values) in series {
+        let mut segments: Vec<Vec<(f64, f64)>> = vec![Vec::new()];
+        for (second, &value) in values.iter().enumerate() {
+            match segments.last_mut() {
+                Some(segment) if !value.is_nan() => segment.push((x(second), y(value))),
+                Some(segment) if !segment.is_empty() => segments.push(Vec::new()),
+                _ => {}
+            }
+        }
+        for segment in segments {
+            match segment[..] {
+                [] => {}
+                // A line needs two points, so a lone second is drawn as a dot
+                [(x, y)] => {
+                    let _ = write!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\" fill=\"{}\"/>", x, y, colour);
+                }
+                _ => {
+                    let points: Vec<String> = segment.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+                    let _ = write!(
+                        svg,
+                        "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>",
+                        colour,
+                        points.join(" ")
+                    );
+                }
+            }
+        }
+    }
+    svg.push_str("</svg>\n<p class=\"legend\">");
+    for (name, colour, _) in series {
+        let _ = write!(svg, "<span style=\"color:{}\">&#9632; {}</span>", colour, escape(name));
+    }
+    svg.push_str("</p>\n");
+    svg
+}
+
+/// One bar per labelled value
+fn bar_chart(bars: &[(String, f64)], colour: &str) -> String {
+    let top = nice_max(bars.iter().map(|(_, value)| *value));
+    let slot = (WIDTH - 2.0 * MARGIN) / bars.len().max(1) as f64;
+
+    let mut svg = svg_start();
+    svg.push_str(&axes(top, ""));
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let height = value / top * (HEIGHT - MARGIN);
+        let left = MARGIN + i as f64 * slot;
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{}: {}</title></rect>",
+            left + slot * 0.1,
+            HEIGHT - MARGIN / 2.0 - height,
+            slot * 0.8,
+            height,
+            colour,
+            escape(label),
+            value
+        );
+        // Label every bar when they fit, otherwise just the first and last
+        if bars.len() <= 8 || i == 0 || i == bars.len() - 1 {
+            let _ = write!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.0}\" text-anchor=\"middle\">{}</text>",
+                left + slot / 2.0,
+                HEIGHT - 4.0,
+                escape(label.split(" - ").next().unwrap_or(label))
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn svg_start() -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = WIDTH,
+        h = HEIGHT
+    )
+}
+
+/// Baseline and top line with the value scale
+fn axes(top: f64, unit: &str) -> String {
+    let (bottom, roof) = (HEIGHT - MARGIN / 2.0, MARGIN / 2.0);
+    format!(
+        "<line x1=\"{m}\" y1=\"{bottom}\" x2=\"{r}\" y2=\"{bottom}\" stroke=\"#999\"/>\
+        <line x1=\"{m}\" y1=\"{roof}\" x2=\"{r}\" y2=\"{roof}\" stroke=\"#ddd\"/>\
+        <text x=\"{l}\" y=\"{bottom}\" text-anchor=\"end\">0</text>\
+        <text x=\"{l}\" y=\"{roof}\" text-anchor=\"end\">{top} {unit}</text>",
+        m = MARGIN,
+        r = WIDTH - MARGIN,
+        l = MARGIN - 4.0,
+    )
+}
+
+/// Smallest 1, 2 or 5 times a power of ten that is at least the largest value
+fn nice_max(values: impl Iterator<Item = f64>) -> f64 {
+    let largest = values.fold(0.0, f64::max);
+    if largest <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(largest.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|step| step * magnitude)
+        .find(|&max| max >= largest)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Escapes text for use in HTML or XML, including attribute values
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_test::LoadTestConfig;
+    use crate::outcome::RequestOutcome;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn scale_rounds_up_to_a_nice_number() {
+        assert_eq!(nice_max([0.0].into_iter()), 1.0);
+        assert_eq!(nice_max([3.2, 1.0].into_iter()), 5.0);
+        assert_eq!(nice_max([120.0].into_iter()), 200.0);
+        assert_eq!(nice_max([1000.0].into_iter()), 1000.0);
+    }
+
+    #[test]
+    fn empty_seconds_leave_a_gap_in_the_line() {
+        let svg = line_chart(&[("Average", BLUE, &[10.0, f64::NAN, 20.0, 30.0, f64::NAN])], "ms");
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert_eq!(svg.matches("<polyline").count(), 1);
+        let points = svg.split("points=\"").nth(1).unwrap().split('"').next().unwrap();
+        assert_eq!(points.split(' ').count(), 2);
+        assert!(!svg.contains("NaN"));
+    }
+
+    #[test]
+    fn report_is_a_single_escaped_page_with_charts() {
+        let outcomes: Vec<RequestOutcome> = (0..4)
+            .map(|i| RequestOutcome {
+                index: i,
+                worker: 0,
+                url: "http://localhost/?a=1&b=2".to_string(),
+                step: None,
+                endpoint: None,
+                start: Duration::from_millis(i * 600),
+                scheduled: None,
+                duration: Duration::from_millis(10 + i),
+                status: Some(200),
+                bytes: 2,
+                error: None,
+                phases: Default::default(),
+            })
+            .collect();
+        let mut report = Report::new(LoadTestConfig::new("http://localhost/?a=1&b=2"), UNIX_EPOCH);
+        outcomes.iter().for_each(|outcome| report.record(outcome));
+        report.elapsed = Duration::from_secs(3);
+        let html = render(&report);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("http://localhost/?a=1&amp;b=2"));
+        assert!(!html.contains("a=1&b"));
+        assert_eq!(html.matches("<svg").count(), 4);
+        assert!(!html.contains("<script"));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::client::Response;
+use crate::Method;
+use crate::status::ExpectedStatus;
+use crate::template::Template;
+use http::header::{HeaderName, SET_COOKIE};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// One request of a multi-step journey such as login, then load the dashboard,
+/// then place an order. The URL, header values and payload are templates, so
+/// they can also use values extracted by earlier steps as `{{name}}`.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,
+    pub method: Method,
+    /// Absolute, or relative to the URL of the test
+    pub url: String,
+    pub headers: Vec<(HeaderName, String)>,
+    pub payload: Option<String>,
+    /// Replaces the test's expected status codes for this step
+    pub expected_status: Option<ExpectedStatus>,
+    pub extract: Vec<Extract>,
+}
+
+/// Stores a value from a step's response in a variable
+#[derive(Debug, Clone)]
+pub struct Extract {
+    pub variable: String,
+    pub source: Source,
+}
+
+/// Where in a response an extracted value comes from
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// JSON pointer into the body, e.g. `/user/id`
+    JsonPointer(String),
+    /// First capture group of a regex run on the body, or the whole match
+    /// when it has no groups
+    Regex(Regex),
+    Header(HeaderName),
+    /// Value of a cookie set with `Set-Cookie`
+    Cookie(String),
+}
+
+impl Step {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            method: Method::Get,
+            url: url.into(),
+            headers: Vec::new(),
+            payload: None,
+            expected_status: None,
+            extract: Vec::new(),
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// JSON body. A JSON Content-Type is added unless the headers set one.
+    pub fn payload(mut self, payload: impl Into<String>) -> Self {
+        self.payload = Some(payload.into());
+        self
+    }
+
+    pub fn expected_status(mut self, expected_status: ExpectedStatus) -> Self {
+        self.expected_status = Some(expected_status);
+        self
+    }
+
+    pub fn extract(mut self, variable: impl Into<String>, source: Source) -> Self {
+        self.extract.push(Extract {
+            variable: variable.into(),
+            source,
+        });
+        self
+    }
+
+    /// Every template in the step: URL, header values and payload
+    fn templates(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str())
+            .chain(self.headers.iter().map(|(_, value)| value.as_str()))
+            .chain(self.payload.as_deref())
+    }
+}
+
+impl Source {
+    /// Parses a regex source, for which invalid patterns are the only error
+    pub fn regex(pattern: &str) -> Result<Self, String> {
+        Regex::new(pattern)
+            .map(Source::Regex)
+            .map_err(|e| format!("Invalid regex {}: {}", pattern, e))
+    }
+
+    pub fn find(&self, response: &Response) -> Option<String> {
+        match self {
+            Source::JsonPointer(pointer) => {
+                let json: Value = serde_json::from_slice(&response.body).ok()?;
+                match json.pointer(pointer)? {
+                    Value::Null => None,
+                    Value::String(value) => Some(value.clone()),
+                    value => Some(value.to_string()),
+                }
+            }
+            Source::Regex(regex) => {
+                let body = String::from_utf8_lossy(&response.body);
+                let captures = regex.captures(&body)?;
+                captures.get(1).or_else(|| captures.get(0)).map(|value| value.as_str().to_string())
+            }
+            Source::Header(name) => response
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+            Source::Cookie(name) => response
+                .headers
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .filter_map(|cookie| cookie.split(';').next()?.split_once('='))
+                .find(|(cookie, _)| cookie.trim() == name)
+                .map(|(_, value)| value.trim().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::JsonPointer(pointer) => write!(f, "JSON pointer {}", pointer),
+            Source::Regex(regex) => write!(f, "regex {}", regex),
+            Source::Header(name) => write!(f, "header {}", name),
+            Source::Cookie(name) => write!(f, "cookie {}", name),
+        }
+    }
+}
+
+/// Checks that steps have unique names and only use variables extracted by
+/// the steps before them
+pub fn check_steps(steps: &[Step]) -> Result<(), String> {
+    let mut extracted: Vec<&str> = Vec::new();
+    for (i, step) in steps.iter().enumerate() {
+        if steps[..i].iter().any(|earlier| earlier.name == step.name) {
+            return Err(format!("Step names must be unique, \"{}\" is used twice", step.name));
+        }
+        for template in step.templates() {
+            let template: Template = template.parse().map_err(|e| format!("Step \"{}\": {}", step.name, e))?;
+            if let Some(name) = template.variables().find(|name| !extracted.contains(name)) {
+                return Err(format!(
+                    "Step \"{}\" uses {{{{{}}}}}, which no earlier step extracts",
+                    step.name, name
+                ));
+            }
+        }
+        extracted.extend(step.extract.iter().map(|extract| extract.variable.as_str()));
+    }
+    Ok(())
+}
+
+/// Where one worker is in the journey and the values it has extracted so far
+#[derive(Debug, Default)]
+pub(crate) struct Journey {
+    step: usize,
+    variables: HashMap<String, String>,
+}
+
+impl Journey {
+    /// Index of the step to send next
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Values extracted so far
+    pub fn variables(&self) -> &HashMap<String, String> {
+        &self.variables
+    }
+
+    /// Stores the values the step extracts from its response. The error names
+    /// the first value that was not found.
+    pub fn extract(&mut self, step: &Step, response: &Response) -> Result<(), String> {
+        for extract in &step.extract {
+            let value = extract.source.find(response).ok_or_else(|| {
+                format!("step {}: no value for {} ({})", step.name, extract.variable, extract.source)
+            })?;
+            self.variables.insert(extract.variable.clone(), value);
+        }
+        Ok(())
+    }
+
+    /// Moves on to the next step. The journey starts again from the first step,
+    /// with no variables, after its last step or after a failure.
+    pub fn advance(&mut self, steps: usize, success: bool) {
+        self.step += 1;
+        if !success || self.step >= steps {
+            self.step = 0;
+            self.variables.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::StatusCode;
+    use http::header::{HeaderMap, HeaderValue};
+
+    fn response() -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("r-1"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("theme=dark"));
+        headers.append(SET_COOKIE, HeaderValue::from_static("session=s3cr3t; Path=/; HttpOnly"));
+        Response {
+            url: url::Url::parse("http://localhost/login").unwrap(),
+            status: StatusCode::OK,
+            headers,
+            body: Bytes::from_static(br#"{"token": "abc123", "user": {"id": 42}, "csrf": "<input value='x9'>"}"#),
+        }
+    }
+
+    #[test]
+    fn values_are_found_in_each_source() {
+        let response = response();
+        let find = |source: Source| source.find(&response);
+        assert_eq!(find(Source::JsonPointer("/token".to_string())).as_deref(), Some("abc123"));
+        assert_eq!(find(Source::JsonPointer("/user/id".to_string())).as_deref(), Some("42"));
+        assert_eq!(find(Source::JsonPointer("/missing".to_string())), None);
+        assert_eq!(find(Source::regex("value='([^']+)'").unwrap()).as_deref(), Some("x9"));
+        assert_eq!(find(Source::Header(HeaderName::from_static("x-request-id"))).as_deref(), Some("r-1"));
+        assert_eq!(find(Source::Cookie("session".to_string())).as_deref(), Some("s3cr3t"));
+        assert_eq!(find(Source::Cookie("other".to_string())), None);
+        assert!(Source::regex("(").is_err());
+    }
+
+    #[test]
+    fn values_are_kept_until_the_journey_starts_again() {
+        let login = Step::new("login", "/login")
+            .extract("token", Source::JsonPointer("/token".to_string()))
+            .extract("id", Source::JsonPointer("/user/id".to_string()));
+        let mut journey = Journey::default();
+        journey.extract(&login, &response()).unwrap();
+        journey.advance(2, true);
+        assert_eq!(journey.step(), 1);
+        assert_eq!(journey.variables()["token"], "abc123");
+        assert_eq!(journey.variables()["id"], "42");
+
+        journey.advance(2, true);
+        assert_eq!(journey.step(), 0);
+        assert!(journey.variables().is_empty());
+    }
+
+    #[test]
+    fn missing_values_are_reported() {
+        let step = Step::new("login", "/").extract("token", Source::JsonPointer("/nope".to_string()));
+        let error = Journey::default().extract(&step, &response()).unwrap_err();
+        assert_eq!(error, "step login: no value for token (JSON pointer /nope)");
+    }
+
+    #[test]
+    fn steps_can_only_use_earlier_values() {
+        let login = Step::new("login", "/login").extract("token", Source::JsonPointer("/token".to_string()));
+        let home = Step::new("home", "/?t={{token}}&u={{csv.user}}&n={{seq}}");
+        assert!(check_steps(&[login.clone(), home.clone()]).is_ok());
+        assert_eq!(
+            check_steps(&[home, login.clone()]).unwrap_err(),
+            "Step \"home\" uses {{token}}, which no earlier step extracts"
+        );
+        assert!(check_steps(&[login.clone(), login.clone()]).is_err());
+        assert!(check_steps(&[login, Step::new("bad", "/{{randInt}}")]).is_err());
+    }
+}
@@ -1,134 +1,328 @@
-use reqwest::blocking;
-use std::{fmt::format, sync::{Arc, Mutex}, time::{Duration, Instant}};
-use std::sync::atomic::{AtomicBool, Ordering};
+use base64::Engine;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, HeaderName, HeaderValue};
+use std::{path::Path, time::Duration};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub mod client;
+pub mod compare;
+pub mod crawl;
+pub mod endpoint;
+mod engine;
+pub mod events;
+pub mod feeder;
+mod html;
+pub mod journey;
+pub mod load_test;
+pub mod outcome;
+pub mod output;
+pub mod profile;
+pub mod samples;
+pub mod scenario;
+pub mod stats;
+pub mod status;
+pub mod template;
+pub mod thresholds;
+
+pub use client::{Client, Session};
+pub use compare::{Comparison, MannWhitney, MetricChange, RunSummary};
+pub use crawl::{CrawlOptions, Crawler};
+pub use endpoint::Endpoint;
+pub use events::{Event, Subscriber};
+pub use feeder::Feeder;
+pub use journey::{Extract, Source, Step};
+pub use load_test::{LoadTest, LoadTestConfig, Progress, Report};
+pub use outcome::{ErrorKind, Phase, PhaseTimings, RequestOutcome};
+pub use output::{ReportFormat, ReportOutput};
+pub use profile::{Arrival, LoadProfile, Rate, RunLength, Schedule, Stage, Stages, format_duration, parse_duration};
+pub use samples::SampleWriter;
+pub use scenario::Scenario;
+pub use stats::Stats;
+pub use status::ExpectedStatus;
+pub use template::Template;
+pub use thresholds::{Threshold, ThresholdResult};
+pub use http::header::HeaderMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Method {
-    Get = 1,
-    Post = 2,
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Head,
+    Options,
+    /// Any other verb, e.g. `PURGE`. Stored upper-case.
+    Custom(String),
 }
 
 impl Method {
-    pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "post" => Method::Post,
-            _ => Method::Get,
+    pub const STANDARD: [Method; 7] = [
+        Method::Get,
+        Method::Post,
+        Method::Put,
+        Method::Patch,
+        Method::Delete,
+        Method::Head,
+        Method::Options,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Head => "HEAD",
+            Method::Options => "OPTIONS",
+            Method::Custom(verb) => verb,
+        }
+    }
+
+    /// Whether a payload should be sent with this method
+    pub fn has_body(&self) -> bool {
+        !matches!(self, Method::Get | Method::Head | Method::Options)
+    }
+
+    fn to_http(&self) -> http::Method {
+        http::Method::from_bytes(self.as_str().as_bytes())
+            .expect("Method verbs are validated when parsed")
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Standard methods are matched case-insensitively. Anything else must be an
+/// upper-case token (e.g. `PURGE`) to be treated as a custom verb, so typos such
+/// as `gett` are reported instead of being sent.
+impl std::str::FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(method) = Method::STANDARD
+            .iter()
+            .find(|m| m.as_str().eq_ignore_ascii_case(s))
+        {
+            return Ok(method.clone());
+        }
+
+        let is_token = !s.is_empty()
+            && s.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+        if is_token && s.as_bytes()[0].is_ascii_uppercase() {
+            Ok(Method::Custom(s.to_string()))
+        } else {
+            Err(format!(
+                "Unknown HTTP method \"{}\". Use get, post, put, patch, delete, head, options \
+                or an upper-case custom verb such as PURGE",
+                s
+            ))
         }
     }
 }
 
 pub fn normalise_url(url: String, force_url: bool) -> String {
-    if force_url {
-        url
-    } else if ["https://", "http://"].iter().any(|s| url.starts_with(*s)) {
+    if force_url || ["https://", "http://"].iter().any(|s| url.starts_with(*s)) {
         url
     } else {
         format!("https://{}", url)
     }
 }
 
-pub fn get_client(timeout: u16, ignore_ssl: bool) -> Arc<blocking::Client> {
-    let client = blocking::Client::builder()
-        .timeout(Duration::from_millis(timeout.into()))
-        .danger_accept_invalid_certs(ignore_ssl)
-        .build()
-        .expect("Failed to build client");
+/// Turns the `--payload` value into a request body.
+/// Accepts either raw JSON or a path to a file containing JSON. Empty input means no body.
+/// Bodies with `{{...}}` templates are checked with `0` in place of each one, which is
+/// valid JSON both as a value and inside a string.
+pub fn resolve_payload(payload: &str) -> Result<Option<String>, String> {
+    let payload = payload.trim();
+    if payload.is_empty() {
+        return Ok(None);
+    }
+
+    let body = if Path::new(payload).is_file() {
+        std::fs::read_to_string(payload)
+            .map_err(|e| format!("Failed to read payload file {}: {}", payload, e))?
+    } else if payload.starts_with('{') || payload.starts_with('[') {
+        payload.to_string()
+    } else {
+        return Err(format!("Payload is neither JSON nor an existing file: {}", payload));
+    };
+
+    let skeleton = if body.contains("{{") { body.parse::<Template>()?.skeleton() } else { body.clone() };
+    serde_json::from_str::<serde_json::Value>(&skeleton).map_err(|e| format!("Payload is not valid JSON: {}", e))?;
 
-    Arc::new(client)
+    Ok(Some(body))
 }
 
-pub fn make_requests<O, E>(
-    url: Arc<String>,
-    number: u32,
-    threads: u32,
-    client: Arc<blocking::Client>,
-    verbose: bool,
-    output: (O, E),
-    times: Arc<Mutex<Vec<u32>>>,
-    cancel_flag: Arc<AtomicBool>,
-    method: Method,
-)
+/// Parses a single `Name: value` header as given to `-H`.
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| format!("Header must be in the form \"Name: value\": {}", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("Invalid header name: {}", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("Invalid value for header {}", name))?;
+    Ok((name, value))
+}
+
+/// Builds the header map sent with every request from `Name: value` strings and
+/// the optional bearer token / `user:password` basic auth shortcuts.
+pub fn build_headers<'a, I>(headers: I, bearer: Option<&str>, basic_auth: Option<&str>) -> Result<HeaderMap, String>
 where
-    O: Fn(String) + Send + Sync + 'static + Clone,
-    E: Fn(String) + Send + Sync + 'static + Clone,
+    I: IntoIterator<Item = &'a str>,
 {
-    let number_per_thread = number / threads;
-    let remainder = number % threads;
-
-    for i in 0..threads {
-        let url_arc = Arc::clone(&url);
-        let client_arc = Arc::clone(&client);
-        let output_clone = output.clone();
-        let times_arc = Arc::clone(&times);
-        let cancel_flag = Arc::clone(&cancel_flag);
-        let requests_for_this_thread = number_per_thread + if i < remainder { 1 } else { 0 };
-        let start_idx = i * number_per_thread + std::cmp::min(i, remainder);
-
-        std::thread::spawn(move || {
-            let (out, err) = output_clone;
-            for j in 0..requests_for_this_thread {
-                if cancel_flag.load(Ordering::SeqCst) {
-                    break;
-                }
-                let idx = start_idx + j;
-                let start = Instant::now();
-
-                let resp = match method {
-                    Method::Post => client_arc.post(url_arc.as_str()).send(),
-                    Method::Get => client_arc.get(url_arc.as_str()).send(),
-                };
-
-                let duration = start.elapsed().as_micros() as u32;
-                let mut times_guard = times_arc.lock().unwrap();
-
-                if resp.is_ok() {
-                    let response = resp.unwrap();
-                    if response.status() == 509 {
-                        out(format!("Website bandwidth limit reached"));
-                    }
-                    if verbose {
-                        out(format!("Status code: {}", response.status()));
-                    }
-                    times_guard[idx as usize] = duration;
-                } else {
-                    if verbose {
-                        let e = resp.unwrap_err();
-                        let err_msg = format!(
-                            "Request failed at idx {}: {:?}\nURL: {}\nThread: {}\nError: {:?}",
-                            idx, e, url_arc, i, e
-                        );
-                        err(err_msg);
-                    } else {
-                        let err_msg = format!(
-                            "Failed Request Number: {} ", idx
-                        );
-                        err(err_msg);
-                    }
-                    times_guard[idx as usize] = u32::MAX; 
-                }
-            }
-        });
+    let mut map = HeaderMap::new();
+    for header in headers {
+        let (name, value) = parse_header(header)?;
+        map.append(name, value);
+    }
+
+    if bearer.is_some() && basic_auth.is_some() {
+        return Err("Only one of bearer token and basic auth can be used".to_string());
+    }
+    let auth = if let Some(token) = bearer {
+        Some(format!("Bearer {}", token.trim()))
+    } else if let Some(credentials) = basic_auth {
+        if !credentials.contains(':') {
+            return Err("Basic auth must be in the form user:password".to_string());
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        Some(format!("Basic {}", encoded))
+    } else {
+        None
+    };
+    if let Some(auth) = auth {
+        let mut value = HeaderValue::from_str(&auth)
+            .map_err(|_| "Authorization value contains invalid characters".to_string())?;
+        value.set_sensitive(true);
+        map.insert(AUTHORIZATION, value);
     }
+
+    Ok(map)
 }
 
-pub fn get_average(times: &Vec<u32>) -> (Duration, u32, Duration) {
-    let mut total: u32 = 0;
-    let mut successes: u32 = 0;
-    let mut fails: u32 = 0;
-    let mut max: u32 = 0;
-    for time in times {
-        if *time == 0 || *time == u32::MAX {
-            fails += 1;
-        } else {
-            successes += 1;
-            total += *time;
-            max = max.max(*time);
+/// Adds a JSON Content-Type unless one was set explicitly.
+pub fn set_json_content_type(headers: &mut HeaderMap) {
+    headers
+        .entry(CONTENT_TYPE)
+        .or_insert(HeaderValue::from_static("application/json"));
+}
+
+/// Converts the timeout given in (possibly fractional) seconds into a `Duration`
+pub fn parse_timeout(seconds: f64) -> Result<Duration, String> {
+    if seconds <= 0.0 {
+        return Err(format!("Timeout must be greater than zero, got {}", seconds));
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("Invalid timeout: {} seconds", seconds))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_over_u16_milliseconds_is_kept() {
+        assert_eq!(parse_timeout(70.0).unwrap(), Duration::from_secs(70));
+        assert_eq!(parse_timeout(3600.0).unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_timeout(0.25).unwrap(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn invalid_timeouts_are_rejected() {
+        assert!(parse_timeout(0.0).is_err());
+        assert!(parse_timeout(-1.0).is_err());
+        assert!(parse_timeout(f64::NAN).is_err());
+        assert!(parse_timeout(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn payloads_are_inline_json_or_files() {
+        assert_eq!(resolve_payload("  "), Ok(None));
+        assert_eq!(resolve_payload(r#" {"item": 42} "#), Ok(Some(r#"{"item": 42}"#.to_string())));
+        assert_eq!(resolve_payload("[1, 2]"), Ok(Some("[1, 2]".to_string())));
+
+        let path = std::env::temp_dir().join(format!("site_tester_payload_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"from": "file"}"#).unwrap();
+        assert_eq!(resolve_payload(&path.display().to_string()), Ok(Some(r#"{"from": "file"}"#.to_string())));
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(resolve_payload(&path.display().to_string()).unwrap_err().starts_with("Payload is not valid JSON"));
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = resolve_payload(&path.display().to_string()).unwrap_err();
+        assert!(missing.starts_with("Payload is neither JSON nor an existing file"), "{}", missing);
+        assert!(resolve_payload(r#"{"item": }"#).unwrap_err().starts_with("Payload is not valid JSON"));
+    }
+
+    #[test]
+    fn templated_payloads_are_checked_around_the_templates() {
+        assert!(resolve_payload(r#"{"id": {{seq}}, "name": "{{csv.name}}", "{{key}}": [{{randInt 1 5}}]}"#).is_ok());
+        assert!(resolve_payload(r#"{"id": {{seq}}, "name": }"#).unwrap_err().starts_with("Payload is not valid JSON"));
+        assert!(resolve_payload(r#"{"name": "{{csv.name}}""#).is_err());
+        assert!(resolve_payload(r#"{"n": {{randInt 5}}}"#).unwrap_err().contains("randInt"));
+    }
+
+    #[test]
+    fn headers_are_parsed_from_name_value_pairs() {
+        let (name, value) = parse_header("X-Client:  load-test ").unwrap();
+        assert_eq!((name.as_str(), value.to_str().unwrap()), ("x-client", "load-test"));
+        assert_eq!(parse_header("X-Empty:").unwrap().1, "");
+        assert!(parse_header("X-Client load-test").unwrap_err().starts_with("Header must be in the form"));
+        assert_eq!(parse_header(": value").unwrap_err(), "Invalid header name: ");
+        assert_eq!(parse_header("Bad Name: value").unwrap_err(), "Invalid header name: Bad Name");
+        assert_eq!(parse_header("X-Client: line\nbreak").unwrap_err(), "Invalid value for header x-client");
+    }
+
+    #[test]
+    fn auth_shortcuts_add_an_authorization_header() {
+        let headers = build_headers(["Accept: text/html", "X-A: 1", "X-A: 2"], Some("abc"), None).unwrap();
+        assert_eq!(headers.get_all("x-a").iter().count(), 2);
+        assert_eq!(headers[AUTHORIZATION], "Bearer abc");
+        assert!(headers[AUTHORIZATION].is_sensitive());
+
+        let headers = build_headers([], None, Some("user:pass")).unwrap();
+        assert_eq!(headers[AUTHORIZATION], "Basic dXNlcjpwYXNz");
+
+        assert_eq!(
+            build_headers([], Some("abc"), Some("user:pass")).unwrap_err(),
+            "Only one of bearer token and basic auth can be used"
+        );
+        assert_eq!(build_headers([], None, Some("user")).unwrap_err(), "Basic auth must be in the form user:password");
+        assert!(build_headers(["broken"], None, None).is_err());
+        assert!(build_headers([], Some("a\nb"), None).is_err());
+    }
+
+    #[test]
+    fn standard_methods_are_parsed_in_any_case() {
+        for method in Method::STANDARD {
+            let verb = method.as_str();
+            assert_eq!(verb.parse::<Method>(), Ok(method.clone()));
+            assert_eq!(verb.to_lowercase().parse::<Method>(), Ok(method.clone()));
+            let mixed: String = verb
+                .chars()
+                .enumerate()
+                .map(|(i, c)| if i % 2 == 0 { c.to_ascii_lowercase() } else { c })
+                .collect();
+            assert_eq!(mixed.parse::<Method>(), Ok(method));
         }
+        assert_eq!(" post ".parse::<Method>(), Ok(Method::Post));
     }
 
-    let average: u32 = if successes > 0 { total / successes } else { 0 };
-    let average_value = Duration::from_micros(average as u64);
-    let max = Duration::from_micros(max as u64);
+    #[test]
+    fn upper_case_tokens_are_custom_methods() {
+        assert_eq!("PURGE".parse::<Method>(), Ok(Method::Custom("PURGE".to_string())));
+        assert_eq!("MY-VERB_2".parse::<Method>(), Ok(Method::Custom("MY-VERB_2".to_string())));
+        assert_eq!(Method::Custom("PURGE".to_string()).to_http(), http::Method::from_bytes(b"PURGE").unwrap());
+    }
 
-    (average_value, fails, max)
-}
\ No newline at end of file
+    #[test]
+    fn invalid_methods_are_rejected() {
+        for invalid in ["", "gett", "Purge", "PUR GE", "2XX", "-GET", "GET/1"] {
+            assert!(invalid.parse::<Method>().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use crate::client::Client;
+use crate::crawl::{CrawlOptions, Crawler};
+use crate::endpoint::Endpoint;
+use crate::events::Subscriber;
+use crate::feeder::Feeder;
+use crate::journey::{Step, check_steps};
+use crate::outcome::RequestOutcome;
+use crate::output::MAX_SAMPLES;
+use crate::profile::{LoadProfile, Rate, RunLength, Stage, Stages};
+use crate::stats::{SecondStats, Stats};
+use crate::status::ExpectedStatus;
+use crate::template::Templates;
+use crate::thresholds::{Threshold, ThresholdResult};
+use crate::{HeaderMap, Method, engine, set_json_content_type};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Workers of a run that does not set them or a rate
+const DEFAULT_WORKERS: u32 = 10;
+
+/// Everything needed to run a load test. Built from a URL with defaults that
+/// match the CLI, then adjusted with the builder methods:
+///
+/// ```no_run
+/// use site_tester::{LoadTest, LoadTestConfig};
+///
+/// let config = LoadTestConfig::new("http://localhost:8080/health").requests(500).workers(20);
+/// let report = LoadTest::start(config).unwrap().wait();
+/// assert_eq!(report.stats.fails(), 0);
+/// ```
+#[derive(Clone)]
+pub struct LoadTestConfig {
+    pub url: String,
+    pub profile: LoadProfile,
+    pub method: Method,
+    /// Body sent with methods that take one
+    pub payload: Option<String>,
+    pub headers: HeaderMap,
+    pub expected_status: ExpectedStatus,
+    pub timeout: Duration,
+    pub ignore_ssl: bool,
+    /// Follow same-site links from page to page when set
+    pub crawl: Option<CrawlOptions>,
+    /// Weighted URLs each request picks from in place of `url`, to mimic a
+    /// real traffic mix
+    pub endpoints: Vec<Endpoint>,
+    /// Journey each worker goes through in place of requests to `url`, which
+    /// step URLs are relative to
+    pub steps: Vec<Step>,
+    /// Data files that templates read rows from as `{{name.column}}`
+    pub feeders: Vec<Arc<Feeder>>,
+    /// Conditions the results are checked against once the run is over
+    pub thresholds: Vec<Threshold>,
+    pub subscribers: Vec<Arc<dyn Subscriber>>,
+    /// Whether `workers` was called. Otherwise rate-based runs get enough
+    /// workers for their rate.
+    workers_set: bool,
+}
+
+impl LoadTestConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            profile: LoadProfile::new(RunLength::Requests(100), DEFAULT_WORKERS),
+            method: Method::Get,
+            payload: None,
+            headers: HeaderMap::new(),
+            expected_status: ExpectedStatus::default(),
+            timeout: Duration::from_secs(10),
+            ignore_ssl: false,
+            crawl: None,
+            endpoints: Vec::new(),
+            steps: Vec::new(),
+            feeders: Vec::new(),
+            thresholds: Vec::new(),
+            subscribers: Vec::new(),
+            workers_set: false,
+        }
+    }
+
+    /// Stop after this many requests
+    pub fn requests(mut self, number: u64) -> Self {
+        self.profile.length = RunLength::Requests(number);
+        self
+    }
+
+    /// Keep sending requests for this long
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.profile.length = RunLength::Duration(duration);
+        self
+    }
+
+    /// Without this, rate-based runs size the workers to their rate
+    pub fn workers(mut self, workers: u32) -> Self {
+        self.profile.workers = workers;
+        self.workers_set = true;
+        self
+    }
+
+    /// Start requests at a fixed rate instead of as fast as the workers allow
+    pub fn rate(mut self, rate: Rate) -> Self {
+        self.profile.rate = Some(rate);
+        self.fit_workers();
+        self
+    }
+
+    /// Ramp the load through stages. Replaces the requests or duration limit.
+    pub fn stages(mut self, stages: Stages) -> Self {
+        self.profile.stages = Some(stages);
+        self.fit_workers();
+        self
+    }
+
+    fn fit_workers(&mut self) {
+        if !self.workers_set {
+            self.profile.workers = DEFAULT_WORKERS;
+            self.profile.fit_workers_to_rate();
+        }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// JSON body. A JSON Content-Type is added unless the headers set one.
+    pub fn payload(mut self, payload: impl Into<String>) -> Self {
+        self.payload = Some(payload.into());
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn expected_status(mut self, expected_status: ExpectedStatus) -> Self {
+        self.expected_status = expected_status;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn ignore_ssl(mut self, ignore_ssl: bool) -> Self {
+        self.ignore_ssl = ignore_ssl;
+        self
+    }
+
+    pub fn follow_links(mut self, options: CrawlOptions) -> Self {
+        self.crawl = Some(options);
+        self
+    }
+
+    /// Adds an endpoint to the traffic mix. The first one also becomes `url`.
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Self {
+        if self.endpoints.is_empty() {
+            self.url = endpoint.url.clone();
+        }
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// Adds a step to the journey. Method and payload then come from the steps.
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Adds a data file that templates read rows from as `{{name.column}}`
+    pub fn feeder(mut self, feeder: Feeder) -> Self {
+        self.feeders.push(Arc::new(feeder));
+        self
+    }
+
+    pub fn threshold(mut self, threshold: Threshold) -> Self {
+        self.thresholds.push(threshold);
+        self
+    }
+
+    /// Sends every `Event` of the run to `subscriber`, e.g. a `Fn(&Event)` closure
+    pub fn subscribe(mut self, subscriber: impl Subscriber + 'static) -> Self {
+        self.subscribers.push(Arc::new(subscriber));
+        self
+    }
+}
+
+/// How far a running test has got
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub completed: u64,
+    pub failed: u64,
+    pub elapsed: Duration,
+    pub length: RunLength,
+    pub finished: bool,
+}
+
+/// A load test running in the background
+pub struct LoadTest {
+    receiver: mpsc::Receiver<RequestOutcome>,
+    /// Statistics of the requests finished so far, shared with the last snapshot taken
+    report: Arc<Report>,
+    cancel_flag: Arc<AtomicBool>,
+    started: Instant,
+    elapsed: Option<Duration>,
+}
+
+impl LoadTest {
+    /// Checks the configuration and starts sending requests
+    pub fn start(mut config: LoadTestConfig) -> Result<Self, String> {
+        if !config.steps.is_empty() && config.crawl.is_some() {
+            return Err("Following links cannot be combined with journey steps".to_string());
+        }
+        if !config.endpoints.is_empty() && (!config.steps.is_empty() || config.crawl.is_some()) {
+            return Err("A traffic mix of several URLs cannot be combined with journey steps or following links".to_string());
+        }
+        check_steps(&config.steps)?;
+        if config.payload.is_some() && config.method.has_body() {
+            set_json_content_type(&mut config.headers);
+        }
+        let templates = Templates::new(&config)?;
+        let crawler = match config.crawl {
+            Some(options) => Some(Arc::new(Crawler::new(&config.url, options)?)),
+            None => None,
+        };
+
+        let client = Arc::new(Client::new(config.timeout, config.ignore_ssl, templates.static_headers.clone()));
+        let (results, receiver) = mpsc::channel();
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        engine::spawn(config.clone(), templates, client, crawler, results, Arc::clone(&cancel_flag));
+
+        Ok(Self {
+            report: Arc::new(Report::new(config, SystemTime::now())),
+            receiver,
+            cancel_flag,
+            started: Instant::now(),
+            elapsed: None,
+        })
+    }
+
+    pub fn config(&self) -> &LoadTestConfig {
+        &self.report.config
+    }
+
+    /// Collects the requests finished since the last call without blocking
+    pub fn progress(&mut self) -> Progress {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(outcome) => self.collect(outcome),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.finish();
+                    break;
+                }
+            }
+        }
+        Progress {
+            completed: self.report.stats.total(),
+            failed: self.report.stats.fails(),
+            elapsed: self.elapsed.unwrap_or_else(|| self.started.elapsed()),
+            length: self.report.config.profile.run_length(),
+            finished: self.elapsed.is_some(),
+        }
+    }
+
+    /// Stops handing out new requests. Requests already in flight still finish.
+    pub fn cancel(&self) {
+        self.cancel_flag.store(true, Ordering::SeqCst);
+    }
+
+    /// Blocks until every worker has stopped
+    pub fn wait(mut self) -> Report {
+        while self.elapsed.is_none() {
+            match self.receiver.recv() {
+                Ok(outcome) => self.collect(outcome),
+                Err(_) => self.finish(),
+            }
+        }
+        let report = self.report();
+        drop(self);
+        Arc::unwrap_or_clone(report)
+    }
+
+    /// Snapshot of the requests finished so far. Taking one is cheap, but
+    /// holding on to it while the run goes on makes the next update copy it.
+    pub fn report(&mut self) -> Arc<Report> {
+        let elapsed = self.elapsed.unwrap_or_else(|| self.started.elapsed());
+        let cancelled = self.cancel_flag.load(Ordering::SeqCst);
+        if self.report.elapsed != elapsed || self.report.cancelled != cancelled {
+            let report = Arc::make_mut(&mut self.report);
+            report.elapsed = elapsed;
+            report.cancelled = cancelled;
+        }
+        Arc::clone(&self.report)
+    }
+
+    fn collect(&mut self, outcome: RequestOutcome) {
+        Arc::make_mut(&mut self.report).record(&outcome);
+    }
+
+    fn finish(&mut self) {
+        self.elapsed = Some(self.started.elapsed());
+    }
+}
+
+impl Drop for LoadTest {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Results of a finished (or cancelled) load test. Each request is recorded
+/// into the overall statistics and the breakdowns the reports show as it
+/// finishes, so memory use does not grow with the number of requests, only
+/// by a small summary for each second of the run.
+#[derive(Clone)]
+pub struct Report {
+    /// The configuration the test ran with
+    pub config: LoadTestConfig,
+    pub started_at: SystemTime,
+    pub stats: Stats,
+    pub elapsed: Duration,
+    pub cancelled: bool,
+    per_stage: Vec<Stats>,
+    per_step: Vec<Stats>,
+    per_endpoint: Vec<Stats>,
+    /// Only filled in for runs that follow links
+    per_url: BTreeMap<String, Stats>,
+    /// Indexed by the second each request started in
+    per_second: Vec<SecondStats>,
+    /// Latencies of every `sample_every`th successful request, in microseconds
+    samples: Vec<u64>,
+    sample_every: u64,
+}
+
+impl Report {
+    /// An empty report for a run of `config` starting at `started_at`
+    pub fn new(config: LoadTestConfig, started_at: SystemTime) -> Self {
+        let stages = config.profile.stages.as_ref().map_or(0, |stages| stages.0.len());
+        Self {
+            per_stage: vec![Stats::new(); stages],
+            per_step: vec![Stats::new(); config.steps.len()],
+            per_endpoint: vec![Stats::new(); config.endpoints.len()],
+            per_url: BTreeMap::new(),
+            per_second: Vec::new(),
+            samples: Vec::new(),
+            sample_every: 1,
+            config,
+            started_at,
+            stats: Stats::new(),
+            elapsed: Duration::ZERO,
+            cancelled: false,
+        }
+    }
+
+    /// Adds a finished request to the statistics
+    pub fn record(&mut self, outcome: &RequestOutcome) {
+        if outcome.is_success() {
+            if self.stats.successes().is_multiple_of(self.sample_every) {
+                self.samples.push(outcome.duration.as_micros() as u64);
+            }
+            // Keep every other sample once there are too many, and half as many from now on
+            if self.samples.len() > MAX_SAMPLES {
+                self.samples = self.samples.iter().copied().step_by(2).collect();
+                self.sample_every *= 2;
+            }
+        }
+        self.stats.record_outcome(outcome);
+
+        if let Some(stages) = &self.config.profile.stages {
+            let stage = stages.stage_at(outcome.start).unwrap_or(stages.0.len().saturating_sub(1));
+            if let Some(stats) = self.per_stage.get_mut(stage) {
+                stats.record_outcome(outcome);
+            }
+        }
+        if let Some(stats) = outcome.step.and_then(|step| self.per_step.get_mut(step)) {
+            stats.record_outcome(outcome);
+        }
+        if let Some(stats) = outcome.endpoint.and_then(|endpoint| self.per_endpoint.get_mut(endpoint)) {
+            stats.record_outcome(outcome);
+        }
+        if self.crawled() {
+            self.per_url.entry(outcome.url.clone()).or_default().record_outcome(outcome);
+        }
+        let second = outcome.start.as_secs() as usize;
+        if second >= self.per_second.len() {
+            self.per_second.resize_with(second + 1, SecondStats::new);
+        }
+        self.per_second[second].record_outcome(outcome);
+    }
+
+    /// Whether the run followed links across pages
+    pub fn crawled(&self) -> bool {
+        self.config.crawl.is_some()
+    }
+
+    pub fn finished_at(&self) -> SystemTime {
+        self.started_at + self.elapsed
+    }
+
+    /// Statistics for each page, for runs that followed links
+    pub fn per_url(&self) -> &BTreeMap<String, Stats> {
+        &self.per_url
+    }
+
+    /// Each journey step by name, with the statistics of its requests
+    pub fn per_step(&self) -> Vec<(&str, &Stats)> {
+        let names = self.config.steps.iter().map(|step| step.name.as_str());
+        names.zip(&self.per_step).collect()
+    }
+
+    /// Each endpoint of the traffic mix with the statistics of its requests
+    pub fn per_endpoint(&self) -> Vec<(&Endpoint, &Stats)> {
+        self.config.endpoints.iter().zip(&self.per_endpoint).collect()
+    }
+
+    /// Each stage of a staged run with the statistics of the requests started in it
+    pub fn per_stage(&self) -> Vec<(Stage, &Stats)> {
+        match &self.config.profile.stages {
+            Some(stages) => stages.0.iter().copied().zip(&self.per_stage).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Statistics for each second of the run, by when each request started.
+    /// Seconds without any requests are included, so the index is the second.
+    pub fn per_second(&self) -> &[SecondStats] {
+        &self.per_second
+    }
+
+    /// Latencies of successful requests in microseconds, at most `MAX_SAMPLES`
+    /// of them. Longer runs keep an evenly spaced subset.
+    pub fn samples(&self) -> &[u64] {
+        &self.samples
+    }
+
+    /// Checks every threshold of the config against the overall statistics
+    pub fn check_thresholds(&self) -> Vec<ThresholdResult> {
+        self.config.thresholds.iter().map(|threshold| threshold.check(&self.stats)).collect()
+    }
+
+    /// Explains that the generator fell behind the target rate, if it did
+    pub fn rate_warning(&self) -> Option<String> {
+        self.config.profile.rate.and_then(|rate| self.stats.rate_warning(&rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+    use crate::profile::Arrival;
+    use std::io::{Read, Write};
+    use std::sync::atomic::AtomicU64;
+    use std::net::TcpListener;
+
+    /// Answers every request on every connection with a small 200 response
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                std::thread::spawn(move || {
+                    let mut buffer = [0; 1024];
+                    while matches!(stream.read(&mut buffer), Ok(read) if read > 0) {
+                        let response = b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                        if stream.write_all(response).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        format!("http://{}/", address)
+    }
+
+    #[test]
+    fn workers_are_sized_to_the_rate_unless_set() {
+        let rate = |s| Rate::parse(s, Arrival::Constant).unwrap();
+        let config = LoadTestConfig::new("http://localhost/");
+        assert_eq!(config.clone().profile.workers, 10);
+        assert_eq!(config.clone().rate(rate("5")).profile.workers, 10);
+        assert_eq!(config.clone().rate(rate("50")).profile.workers, 50);
+        assert_eq!(config.clone().rate(rate("50")).stages("10s:200,10s:20".parse().unwrap()).profile.workers, 200);
+        assert_eq!(config.clone().rate(rate("500")).stages("10s:20".parse().unwrap()).profile.workers, 500);
+        assert_eq!(config.clone().rate(rate("5000")).profile.workers, 1000);
+        assert_eq!(config.clone().workers(4).rate(rate("50")).profile.workers, 4);
+        assert_eq!(config.rate(rate("50")).workers(4).profile.workers, 4);
+    }
+
+    #[test]
+    fn runs_to_completion_and_reports() {
+        let config = LoadTestConfig::new(serve()).requests(25).workers(4);
+        let report = LoadTest::start(config).unwrap().wait();
+        assert_eq!(report.stats.total(), 25);
+        assert_eq!(report.stats.fails(), 0);
+        assert_eq!(report.stats.status_codes().get(&200), Some(&25));
+        assert!(!report.cancelled);
+    }
+
+    #[test]
+    fn subscribers_see_every_request_then_the_end_of_the_run() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        let config = LoadTestConfig::new(serve())
+            .requests(10)
+            .workers(3)
+            .subscribe(move |event: &Event| seen.lock().unwrap().push(event.clone()));
+        LoadTest::start(config).unwrap().wait();
+
+        let events = events.lock().unwrap();
+        let count = |matches: fn(&Event) -> bool| events.iter().filter(|&e| matches(e)).count();
+        assert_eq!(count(|e| matches!(e, Event::RequestStarted { .. })), 10);
+        assert_eq!(count(|e| matches!(e, Event::RequestCompleted { status: 200, .. })), 10);
+        assert_eq!(count(|e| matches!(e, Event::WorkerFinished { .. })), 3);
+        assert!(matches!(events.last(), Some(Event::RunFinished { .. })));
+    }
+
+    #[test]
+    fn requests_that_cannot_be_filled_in_are_not_started() {
+        use crate::journey::Source;
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        let config = LoadTestConfig::new(serve())
+            .requests(2)
+            .workers(1)
+            .step(Step::new("home", "/").extract("word", Source::regex("o(k)").unwrap()))
+            .step(Step::new("broken", "http://{{word}}:99999/"))
+            .subscribe(move |event: &Event| seen.lock().unwrap().push(event.clone()));
+        let report = LoadTest::start(config).unwrap().wait();
+
+        assert_eq!(report.stats.fails(), 1);
+        let events = events.lock().unwrap();
+        let started: Vec<u64> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::RequestStarted { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(started, vec![0]);
+        assert!(events.iter().any(|event| matches!(event, Event::RequestFailed { index: 1, .. })));
+    }
+
+    #[test]
+    fn cancelled_runs_stop_early() {
+        let config = LoadTestConfig::new(serve()).duration(Duration::from_secs(60)).workers(2);
+        let test = LoadTest::start(config).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        test.cancel();
+        let report = test.wait();
+        assert!(report.cancelled);
+        assert!(report.elapsed < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn journeys_send_steps_in_order_and_report_each() {
+        use crate::journey::Source;
+        use crate::outcome::ErrorKind;
+
+        let urls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&urls);
+        let config = LoadTestConfig::new(serve())
+            .requests(3)
+            .workers(1)
+            .step(Step::new("home", "/").extract("word", Source::regex("o(k)").unwrap()))
+            .step(Step::new("item", "/items/{{word}}").extract("id", Source::JsonPointer("/id".to_string())))
+            .subscribe(move |event: &Event| {
+                if let Event::RequestStarted { url, .. } = event {
+                    seen.lock().unwrap().push(url.rsplit('/').next().unwrap().to_string());
+                }
+            });
+        let report = LoadTest::start(config).unwrap().wait();
+
+        let urls = urls.lock().unwrap();
+        assert_eq!(*urls, vec!["", "k", ""]);
+        let per_step: Vec<(&str, u64, u64)> = report
+            .per_step()
+            .into_iter()
+            .map(|(name, stats)| (name, stats.total(), stats.fails()))
+            .collect();
+        assert_eq!(per_step, vec![("home", 2, 0), ("item", 1, 1)]);
+        assert_eq!(report.stats.errors().get(&ErrorKind::Extract), Some(&1));
+    }
+
+    #[test]
+    fn traffic_mixes_spread_requests_by_weight() {
+        let homes = Arc::new(AtomicU64::new(0));
+        let seen = Arc::clone(&homes);
+        let url = serve();
+        let config = LoadTestConfig::new("http://unused/")
+            .endpoint(Endpoint::new(format!("{}home", url), 3))
+            .endpoint(Endpoint::new(format!("{}search?q={{{{seq}}}}", url), 1))
+            .requests(400)
+            .workers(4)
+            .subscribe(move |event: &Event| {
+                if matches!(event, Event::RequestStarted { url, .. } if url.ends_with("/home")) {
+                    seen.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        assert_eq!(config.url, format!("{}home", url));
+        let report = LoadTest::start(config).unwrap().wait();
+
+        let per_endpoint = report.per_endpoint();
+        assert_eq!(per_endpoint.len(), 2);
+        assert_eq!(per_endpoint[0].1.total() + per_endpoint[1].1.total(), 400);
+        assert!((240..360).contains(&per_endpoint[0].1.total()), "{}", per_endpoint[0].1.total());
+        assert_eq!(homes.load(Ordering::SeqCst), per_endpoint[0].1.total());
+
+        let crawl = LoadTestConfig::new(url.clone()).endpoint(Endpoint::new(url, 1)).follow_links(CrawlOptions::default());
+        assert!(LoadTest::start(crawl).is_err());
+    }
+
+    #[test]
+    fn long_runs_keep_an_evenly_spaced_subset_of_samples() {
+        let mut report = Report::new(LoadTestConfig::new("http://localhost/"), SystemTime::now());
+        for i in 0..MAX_SAMPLES as u64 * 2 + 2 {
+            report.record(&RequestOutcome {
+                index: i,
+                worker: 0,
+                url: "http://localhost/".to_string(),
+                step: None,
+                endpoint: None,
+                start: Duration::ZERO,
+                scheduled: None,
+                duration: Duration::from_micros(i),
+                status: Some(200),
+                bytes: 2,
+                error: None,
+                phases: Default::default(),
+            });
+        }
+        let samples = report.samples();
+        assert_eq!(samples.len(), MAX_SAMPLES / 2 + 1);
+        assert_eq!(samples[..3], [0, 4, 8]);
+        assert_eq!(report.per_second().len(), 1);
+    }
+
+    #[test]
+    fn invalid_urls_are_rejected() {
+        assert!(LoadTest::start(LoadTestConfig::new("not a url")).is_err());
+    }
+}
@@ -2,11 +2,11 @@ mod gui;
 mod cli;
 
 fn main() {
-    let cli_flag = std::env::args().any(|arg| arg == "--cli");
+    let cli_flag = std::env::args().any(|arg| arg == "--cli")
+        || std::env::args().nth(1).is_some_and(|arg| arg == "compare");
 
     if !cli_flag {
         gui::run_gui().unwrap();
-        return;
     } else {
         cli::run_cli();
     }
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Why a request failed. `Status` is a response with an unexpected status
+/// code and `Extract` a journey step whose response lacked a value to extract.
+/// Everything else is a transport error where no usable response arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorKind {
+    Status,
+    Extract,
+    Timeout,
+    Connect,
+    Redirect,
+    Body,
+    Request,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn is_transport(&self) -> bool {
+        !matches!(self, ErrorKind::Status | ErrorKind::Extract)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Status => "status",
+            ErrorKind::Extract => "extract",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Redirect => "redirect",
+            ErrorKind::Body => "body",
+            ErrorKind::Request => "request",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where the time of a request went. DNS, connect and TLS are `None` when the
+/// request reused an open connection (and TLS for plain HTTP). Phases add up
+/// across redirects.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimings {
+    /// Resolving the host name
+    pub dns: Option<Duration>,
+    /// Opening the TCP connection
+    pub connect: Option<Duration>,
+    /// TLS handshake
+    pub tls: Option<Duration>,
+    /// From sending the request until the response headers arrived
+    pub ttfb: Duration,
+    /// Reading the response body
+    pub download: Duration,
+}
+
+/// A phase of a request, in the order they happen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+    FirstByte,
+    Download,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [Phase::Dns, Phase::Connect, Phase::Tls, Phase::FirstByte, Phase::Download];
+
+    /// Short machine-readable name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Dns => "dns",
+            Phase::Connect => "connect",
+            Phase::Tls => "tls",
+            Phase::FirstByte => "ttfb",
+            Phase::Download => "download",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Phase::Dns => "DNS lookup",
+            Phase::Connect => "TCP connect",
+            Phase::Tls => "TLS handshake",
+            Phase::FirstByte => "Time to first byte",
+            Phase::Download => "Download",
+        }
+    }
+
+    /// Whether the phase only happens when a new connection is opened
+    pub fn is_connection_setup(&self) -> bool {
+        matches!(self, Phase::Dns | Phase::Connect | Phase::Tls)
+    }
+}
+
+impl PhaseTimings {
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        match phase {
+            Phase::Dns => self.dns,
+            Phase::Connect => self.connect,
+            Phase::Tls => self.tls,
+            Phase::FirstByte => Some(self.ttfb),
+            Phase::Download => Some(self.download),
+        }
+    }
+
+    pub fn add_dns(&mut self, duration: Duration) {
+        self.dns = Some(self.dns.unwrap_or_default() + duration);
+    }
+
+    pub fn add_connect(&mut self, duration: Duration) {
+        self.connect = Some(self.connect.unwrap_or_default() + duration);
+    }
+
+    pub fn add_tls(&mut self, duration: Duration) {
+        self.tls = Some(self.tls.unwrap_or_default() + duration);
+    }
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.dns, self.connect) {
+            (Some(dns), Some(connect)) => write!(f, "dns {:?}, connect {:?}, ", dns, connect)?,
+            (Some(dns), None) => write!(f, "dns {:?}, ", dns)?,
+            _ => f.write_str("reused connection, ")?,
+        }
+        if let Some(tls) = self.tls {
+            write!(f, "tls {:?}, ", tls)?;
+        }
+        write!(f, "ttfb {:?}, download {:?}", self.ttfb, self.download)
+    }
+}
+
+/// Result of a single request, sent from the worker threads once it finishes
+#[derive(Debug, Clone)]
+pub struct RequestOutcome {
+    /// Position of the request in the run
+    pub index: u64,
+    /// Worker thread that made the request
+    pub worker: u32,
+    pub url: String,
+    /// Index of the journey step the request was for
+    pub step: Option<usize>,
+    /// Index of the endpoint of a traffic mix the request was sent to
+    pub endpoint: Option<usize>,
+    /// When the request was sent, relative to the start of the run
+    pub start: Duration,
+    /// When the request should have been sent in a rate-based run
+    pub scheduled: Option<Duration>,
+    /// Time until the full response body was read, or until the request failed
+    pub duration: Duration,
+    pub status: Option<u16>,
+    /// Size of the response body
+    pub bytes: u64,
+    pub error: Option<ErrorKind>,
+    pub phases: PhaseTimings,
+}
+
+impl RequestOutcome {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Latency measured from when the request should have been sent, so time
+    /// spent waiting behind a stalled server counts against it. Only set for
+    /// rate-based runs.
+    pub fn corrected_duration(&self) -> Option<Duration> {
+        self.scheduled
+            .map(|scheduled| self.start.saturating_sub(scheduled) + self.duration)
+    }
+}
+
+impl fmt::Display for RequestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request {} (thread {}) {}: ", self.index, self.worker, self.url)?;
+        match (self.error, self.status) {
+            (Some(ErrorKind::Status), Some(status)) => write!(
+                f,
+                "failed with unexpected status {} in {:?}, {} bytes",
+                status, self.duration, self.bytes
+            ),
+            (Some(error), _) => write!(f, "failed with {} error after {:?}", error, self.duration),
+            (None, Some(status)) => write!(
+                f,
+                "status {} in {:?}, {} bytes ({})",
+                status, self.duration, self.bytes, self.phases
+            ),
+            (None, None) => write!(f, "completed in {:?}", self.duration),
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use crate::html::{self, escape};
+use crate::load_test::Report;
+use crate::outcome::Phase;
+use crate::profile::RunLength;
+use crate::stats::{PERCENTILES, Stats};
+use serde_json::{Map, Value, json};
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Most raw latencies kept in a JSON report. Longer runs keep an evenly spaced subset.
+pub const MAX_SAMPLES: usize = 100_000;
+
+/// Machine-readable formats a report can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    /// A single offline page with charts
+    Html,
+    /// JUnit XML with one test case per threshold, for CI
+    Junit,
+}
+
+impl ReportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Html => "html",
+            ReportFormat::Junit => "junit",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "html" => Ok(ReportFormat::Html),
+            "junit" => Ok(ReportFormat::Junit),
+            _ => Err(format!("Unknown report format \"{}\". Use json, html or junit", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where to write a report, parsed from `FORMAT` (standard output) or `FORMAT=PATH`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportOutput {
+    pub format: ReportFormat,
+    pub path: Option<PathBuf>,
+}
+
+impl ReportOutput {
+    pub fn to_stdout(&self) -> bool {
+        self.path.is_none()
+    }
+
+    /// Renders `report` and writes it out
+    pub fn write(&self, report: &Report) -> Result<(), String> {
+        let contents = match self.format {
+            ReportFormat::Json => {
+                let mut json = serde_json::to_string_pretty(&json_report(report)).expect("JSON values always serialise");
+                json.push('\n');
+                json
+            }
+            ReportFormat::Html => html::render(report),
+            ReportFormat::Junit => junit_report(report),
+        };
+        match &self.path {
+            Some(path) => std::fs::write(path, contents)
+                .map_err(|e| format!("Failed to write {} report to {}: {}", self.format, path.display(), e)),
+            None => std::io::stdout()
+                .write_all(contents.as_bytes())
+                .map_err(|e| format!("Failed to write {} report: {}", self.format, e)),
+        }
+    }
+}
+
+impl FromStr for ReportOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = match s.split_once('=') {
+            Some((_, "")) => return Err(format!("Missing path in \"{}\"", s)),
+            Some((format, path)) => (format, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+        Ok(Self {
+            format: format.trim().parse()?,
+            path,
+        })
+    }
+}
+
+/// Structured version of a report: the configuration used, when it ran, totals,
+/// latency percentiles, status codes, errors, a per-second time series and the
+/// raw latencies of successful requests for comparing runs. Durations are in
+/// milliseconds, apart from the raw samples which are in microseconds.
+pub fn json_report(report: &Report) -> Value {
+    let config = &report.config;
+    let profile = &config.profile;
+    let stats = &report.stats;
+
+    let (requests, duration) = match profile.run_length() {
+        RunLength::Requests(number) => (Some(number), None),
+        RunLength::Duration(duration) => (None, Some(duration.as_secs_f64())),
+    };
+    let headers: Vec<&str> = config.headers.keys().map(|name| name.as_str()).collect();
+
+    let mut json = json!({
+        "url": config.url,
+        "config": {
+            "method": config.method.as_str(),
+            "workers": profile.pool_size(),
+            "requests": requests,
+            "duration_secs": duration,
+            "rate": profile.rate.map(|rate| json!({
+                "per_second": rate.per_second,
+                "arrival": rate.arrival.to_string(),
+            })),
+            "stages": profile.stages.as_ref().map(|stages| stages.to_string()),
+            "timeout_secs": config.timeout.as_secs_f64(),
+            "expected_status": config.expected_status.to_string(),
+            "ignore_ssl": config.ignore_ssl,
+            "headers": headers,
+            "follow_links": config.crawl.map(|crawl| json!({
+                "max_depth": crawl.max_depth,
+                "max_pages": crawl.max_pages,
+            })),
+            "endpoints": config
+                .endpoints
+                .iter()
+                .map(|endpoint| json!({ "url": endpoint.url, "weight": endpoint.weight }))
+                .collect::<Vec<_>>(),
+            "steps": config
+                .steps
+                .iter()
+                .map(|step| json!({
+                    "name": step.name,
+                    "method": step.method.as_str(),
+                    "url": step.url,
+                }))
+                .collect::<Vec<_>>(),
+            "feeders": config
+                .feeders
+                .iter()
+                .map(|feeder| json!({
+                    "name": feeder.name(),
+                    "strategy": feeder.strategy().as_str(),
+                    "rows": feeder.len(),
+                    "columns": feeder.columns(),
+                }))
+                .collect::<Vec<_>>(),
+        },
+        "started_at": format_timestamp(report.started_at),
+        "finished_at": format_timestamp(report.finished_at()),
+        "elapsed_secs": report.elapsed.as_secs_f64(),
+        "cancelled": report.cancelled,
+        "totals": {
+            "requests": stats.total(),
+            "successes": stats.successes(),
+            "failures": stats.fails(),
+            "http_errors": stats.http_errors(),
+            "transport_errors": stats.transport_errors(),
+            "throughput": stats.throughput(),
+            "late_starts": stats.late_starts(),
+        },
+        "latency_ms": latency(stats),
+        "status_codes": stats
+            .status_codes()
+            .iter()
+            .map(|(status, count)| (status.to_string(), json!(count)))
+            .collect::<Map<_, _>>(),
+        "errors": stats
+            .errors()
+            .iter()
+            .map(|(kind, count)| (kind.as_str().to_string(), json!(count)))
+            .collect::<Map<_, _>>(),
+        "time_series": report
+            .per_second()
+            .iter()
+            .enumerate()
+            .map(|(second, stats)| json!({
+                "second": second,
+                "requests": stats.total(),
+                "failures": stats.fails(),
+                "mean_ms": millis(stats.mean()),
+                "p95_ms": millis(stats.percentile(95.0)),
+            }))
+            .collect::<Vec<_>>(),
+        "warnings": report.rate_warning().into_iter().collect::<Vec<_>>(),
+        "samples_us": report.samples(),
+        "thresholds": report
+            .check_thresholds()
+            .iter()
+            .map(|result| json!({
+                "threshold": result.threshold.to_string(),
+                "actual": result.actual,
+                "passed": result.passed,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    let fields = json.as_object_mut().expect("The report is an object");
+    if stats.has_corrected() {
+        fields.insert("corrected_latency_ms".to_string(), corrected_latency(stats));
+    }
+    let phases: Map<_, _> = Phase::ALL
+        .into_iter()
+        .filter(|&phase| stats.phase_count(phase) > 0)
+        .map(|phase| {
+            let summary = json!({
+                "count": stats.phase_count(phase),
+                "mean": millis(stats.phase_mean(phase)),
+                "p95": millis(stats.phase_percentile(phase, 95.0)),
+            });
+            (phase.as_str().to_string(), summary)
+        })
+        .collect();
+    if !phases.is_empty() {
+        fields.insert("phases_ms".to_string(), Value::Object(phases));
+    }
+    let per_stage = report.per_stage();
+    if !per_stage.is_empty() {
+        let stages = per_stage
+            .iter()
+            .map(|(stage, stats)| json!({
+                "duration_secs": stage.duration.as_secs_f64(),
+                "target": stage.target,
+                "requests": stats.total(),
+                "failures": stats.fails(),
+                "latency_ms": latency(stats),
+            }))
+            .collect();
+        fields.insert("stages".to_string(), Value::Array(stages));
+    }
+    let per_endpoint = report.per_endpoint();
+    if !per_endpoint.is_empty() {
+        let endpoints = per_endpoint
+            .iter()
+            .map(|(endpoint, stats)| json!({
+                "url": endpoint.url,
+                "weight": endpoint.weight,
+                "requests": stats.total(),
+                "failures": stats.fails(),
+                "latency_ms": latency(stats),
+            }))
+            .collect();
+        fields.insert("endpoints".to_string(), Value::Array(endpoints));
+    }
+    let per_step = report.per_step();
+    if !per_step.is_empty() {
+        let steps = per_step
+            .iter()
+            .map(|(name, stats)| json!({
+                "name": name,
+                "requests": stats.total(),
+                "failures": stats.fails(),
+                "latency_ms": latency(stats),
+            }))
+            .collect();
+        fields.insert("steps".to_string(), Value::Array(steps));
+    }
+    if report.crawled() {
+        let pages = report
+            .per_url()
+            .iter()
+            .map(|(url, stats)| (url.clone(), json!({
+                "requests": stats.total(),
+                "failures": stats.fails(),
+                "latency_ms": latency(stats),
+            })))
+            .collect();
+        fields.insert("pages".to_string(), Value::Object(pages));
+    }
+    json
+}
+
+/// JUnit XML with a test case for each threshold, failed when it was breached
+pub fn junit_report(report: &Report) -> String {
+    let results = report.check_thresholds();
+    let failures = results.iter().filter(|result| !result.passed).count();
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <testsuites>\n  <testsuite name=\"site_tester thresholds\" tests=\"{}\" failures=\"{}\" \
+        timestamp=\"{}\" time=\"{:.3}\">\n",
+        results.len(),
+        failures,
+        format_timestamp(report.started_at),
+        report.elapsed.as_secs_f64()
+    );
+    for result in &results {
+        let name = escape(&format!("{} ({})", result.threshold, report.config.url));
+        if result.passed {
+            xml.push_str(&format!("    <testcase classname=\"site_tester\" name=\"{}\"/>\n", name));
+        } else {
+            xml.push_str(&format!(
+                "    <testcase classname=\"site_tester\" name=\"{}\">\n      <failure message=\"{}\"/>\n    </testcase>\n",
+                name,
+                escape(&result.to_string())
+            ));
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn latency(stats: &Stats) -> Value {
+    let mut latency = json!({
+        "min": millis(stats.min()),
+        "mean": millis(stats.mean()),
+        "max": millis(stats.max()),
+        "stddev": millis(stats.stddev()),
+    });
+    for percentile in PERCENTILES {
+        latency[format!("p{}", percentile)] = json!(millis(stats.percentile(percentile)));
+    }
+    latency
+}
+
+fn corrected_latency(stats: &Stats) -> Value {
+    let mut latency = json!({
+        "mean": millis(stats.corrected_mean()),
+        "max": millis(stats.corrected_max()),
+    });
+    for percentile in PERCENTILES {
+        latency[format!("p{}", percentile)] = json!(millis(stats.corrected_percentile(percentile)));
+    }
+    latency
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// RFC 3339 timestamp in UTC with millisecond precision, e.g. `2024-05-01T12:30:00.250Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_test::LoadTestConfig;
+    use crate::outcome::{ErrorKind, RequestOutcome};
+
+    fn outcome(start_ms: u64, status: u16, error: Option<ErrorKind>) -> RequestOutcome {
+        RequestOutcome {
+            index: 0,
+            worker: 0,
+            url: "http://localhost/".to_string(),
+            step: None,
+            endpoint: None,
+            start: Duration::from_millis(start_ms),
+            scheduled: None,
+            duration: Duration::from_millis(20),
+            status: Some(status),
+            bytes: 2,
+            error,
+            phases: Default::default(),
+        }
+    }
+
+    #[test]
+    fn outputs_are_parsed() {
+        let output: ReportOutput = "json".parse().unwrap();
+        assert!(output.to_stdout());
+        let output: ReportOutput = "JSON=out/report.json".parse().unwrap();
+        assert_eq!(output.format, ReportFormat::Json);
+        assert_eq!(output.path, Some(PathBuf::from("out/report.json")));
+        assert_eq!("html=report.html".parse::<ReportOutput>().unwrap().format, ReportFormat::Html);
+        assert_eq!("junit=results.xml".parse::<ReportOutput>().unwrap().format, ReportFormat::Junit);
+        assert!("json=".parse::<ReportOutput>().is_err());
+        assert!("xml".parse::<ReportOutput>().is_err());
+    }
+
+    #[test]
+    fn timestamps_are_rfc_3339() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_timestamp(leap_day), "2024-02-29T12:34:56.789Z");
+    }
+
+    #[test]
+    fn json_report_has_totals_statuses_and_time_series() {
+        let outcomes = [
+            outcome(100, 200, None),
+            outcome(900, 200, None),
+            outcome(2500, 500, Some(ErrorKind::Status)),
+        ];
+        let mut report = Report::new(LoadTestConfig::new("http://localhost/").requests(3), UNIX_EPOCH);
+        outcomes.iter().for_each(|outcome| report.record(outcome));
+        report.elapsed = Duration::from_secs(3);
+        let json = json_report(&report);
+        assert_eq!(json["config"]["requests"], 3);
+        assert_eq!(json["finished_at"], "1970-01-01T00:00:03.000Z");
+        assert_eq!(json["totals"]["requests"], 3);
+        assert_eq!(json["totals"]["failures"], 1);
+        assert_eq!(json["status_codes"]["200"], 2);
+        assert_eq!(json["errors"]["status"], 1);
+        assert_eq!(json["latency_ms"]["p50"].as_f64().map(f64::round), Some(20.0));
+        let series = json["time_series"].as_array().unwrap();
+        let requests: Vec<_> = series.iter().map(|second| second["requests"].as_u64().unwrap()).collect();
+        assert_eq!(requests, vec![2, 0, 1]);
+        assert_eq!(json["samples_us"], json!([20_000, 20_000]));
+    }
+
+    #[test]
+    fn junit_report_has_a_case_per_threshold() {
+        let outcomes = [outcome(0, 200, None), outcome(10, 500, Some(ErrorKind::Status))];
+        let config = LoadTestConfig::new("http://localhost/")
+            .threshold("p95<300ms".parse().unwrap())
+            .threshold("error_rate<1%".parse().unwrap());
+        let mut report = Report::new(config, UNIX_EPOCH);
+        outcomes.iter().for_each(|outcome| report.record(outcome));
+        report.elapsed = Duration::from_secs(1);
+        let xml = junit_report(&report);
+        assert!(xml.contains("tests=\"2\" failures=\"1\""));
+        assert!(xml.contains("name=\"p95&lt;300ms (http://localhost/)\"/>"));
+        assert!(xml.contains("<failure message=\"error_rate&lt;1% failed (actual 50.00%)\"/>"));
+    }
+}
@@ -0,0 +1,480 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long a run lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLength {
+    /// Stop after a fixed number of requests, shared between the workers
+    Requests(u64),
+    /// Keep sending requests until the time is up
+    Duration(Duration),
+}
+
+impl fmt::Display for RunLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunLength::Requests(number) => write!(f, "{} requests", number),
+            RunLength::Duration(duration) => write!(f, "{}", format_duration(*duration)),
+        }
+    }
+}
+
+/// How request start times are spread out in a rate-based run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrival {
+    /// Evenly spaced, one request every `1 / rate` seconds
+    Constant,
+    /// Random gaps with the given average, like independent users arriving
+    Poisson,
+}
+
+impl FromStr for Arrival {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "constant" => Ok(Arrival::Constant),
+            "poisson" => Ok(Arrival::Poisson),
+            _ => Err(format!("Unknown arrival model \"{}\". Use constant or poisson", s)),
+        }
+    }
+}
+
+impl fmt::Display for Arrival {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arrival::Constant => f.write_str("constant"),
+            Arrival::Poisson => f.write_str("poisson"),
+        }
+    }
+}
+
+/// Target request rate for an open load model, where requests are started on a
+/// timeline instead of as soon as the previous one finishes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    pub per_second: f64,
+    pub arrival: Arrival,
+}
+
+impl Rate {
+    /// Parses `100`, `100/s` or `600/m`
+    pub fn parse(s: &str, arrival: Arrival) -> Result<Self, String> {
+        let invalid = || format!("Invalid rate \"{}\". Use requests per second like 100 or 100/s, or per minute like 600/m", s);
+        let (number, per) = s.trim().split_once('/').unwrap_or((s.trim(), "s"));
+        let number: f64 = number.trim().parse().map_err(|_| invalid())?;
+        let per_second = match per.trim() {
+            "s" => number,
+            "m" => number / 60.0,
+            "h" => number / 3600.0,
+            _ => return Err(invalid()),
+        };
+        if !per_second.is_finite() || per_second <= 0.0 {
+            return Err(invalid());
+        }
+        Ok(Self { per_second, arrival })
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/s ({})", self.per_second, self.arrival)
+    }
+}
+
+/// Hands out request slots on the timeline of a rate-based run. Each slot is the
+/// request index and the offset from the start of the run it should be sent at.
+pub struct Schedule {
+    rate: Rate,
+    length: RunLength,
+    stages: Option<Stages>,
+    next: Mutex<(u64, Duration)>,
+}
+
+impl Schedule {
+    pub fn new(rate: Rate, length: RunLength) -> Self {
+        Self {
+            rate,
+            length,
+            stages: None,
+            next: Mutex::new((0, Duration::ZERO)),
+        }
+    }
+
+    /// Ramp the rate between stage targets, starting from the base rate
+    pub fn with_stages(mut self, stages: Stages) -> Self {
+        self.length = RunLength::Duration(stages.total_duration());
+        self.stages = Some(stages);
+        self
+    }
+
+    /// The next slot, or `None` once the run has all the requests it needs
+    pub fn next_slot(&self) -> Option<(u64, Duration)> {
+        let mut next = self.next.lock().unwrap();
+        let (index, mut at) = *next;
+        loop {
+            match self.length {
+                RunLength::Requests(number) if index >= number => return None,
+                RunLength::Duration(duration) if at >= duration => return None,
+                _ => {}
+            }
+            if self.rate_at(at) > MIN_RATE {
+                break;
+            }
+            // Nothing to send while a stage holds the rate at zero
+            at += STEP;
+        }
+
+        let gap = match self.rate.arrival {
+            Arrival::Constant => 1.0,
+            Arrival::Poisson => -(1.0 - rand::random::<f64>()).ln(),
+        };
+        *next = (index + 1, self.advance(at, gap));
+        Some((index, at))
+    }
+
+    fn rate_at(&self, at: Duration) -> f64 {
+        match &self.stages {
+            Some(stages) => stages.target_at(at, self.rate.per_second),
+            None => self.rate.per_second,
+        }
+    }
+
+    /// Moves forward from `at` until `requests` worth of the (possibly changing)
+    /// rate has passed
+    fn advance(&self, mut at: Duration, mut requests: f64) -> Duration {
+        loop {
+            let per_second = self.rate_at(at);
+            if per_second > MIN_RATE {
+                let needed = requests / per_second;
+                if self.stages.is_none() || needed <= STEP.as_secs_f64() {
+                    return at + Duration::from_secs_f64(needed);
+                }
+                requests -= per_second * STEP.as_secs_f64();
+            }
+            at += STEP;
+            if matches!(self.length, RunLength::Duration(duration) if at >= duration) {
+                return at;
+            }
+        }
+    }
+}
+
+/// Rates below this are treated as zero
+const MIN_RATE: f64 = 1e-6;
+/// Resolution used to follow a rate that changes over time
+const STEP: Duration = Duration::from_millis(10);
+
+/// One step of a load profile: move linearly to `target` over `duration`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    pub duration: Duration,
+    /// Active workers, or requests per second for rate-based runs
+    pub target: f64,
+}
+
+/// Load profile such as `30s:10,2m:50,30s:0`, ramping from one target to the
+/// next over each stage's duration
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stages(pub Vec<Stage>);
+
+impl Stages {
+    pub fn total_duration(&self) -> Duration {
+        self.0.iter().map(|stage| stage.duration).sum()
+    }
+
+    /// Index of the stage running at `elapsed`
+    pub fn stage_at(&self, elapsed: Duration) -> Option<usize> {
+        let mut end = Duration::ZERO;
+        for (i, stage) in self.0.iter().enumerate() {
+            end += stage.duration;
+            if elapsed < end {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// Target at `elapsed`, interpolated from the previous stage's target
+    /// (or `start` for the first stage)
+    pub fn target_at(&self, elapsed: Duration, start: f64) -> f64 {
+        let mut from = start;
+        let mut stage_start = Duration::ZERO;
+        for stage in &self.0 {
+            let stage_end = stage_start + stage.duration;
+            if elapsed < stage_end {
+                let progress = (elapsed - stage_start).as_secs_f64() / stage.duration.as_secs_f64();
+                return from + (stage.target - from) * progress;
+            }
+            from = stage.target;
+            stage_start = stage_end;
+        }
+        from
+    }
+
+    /// Highest target in the profile, including the starting value
+    pub fn peak(&self, start: f64) -> f64 {
+        self.0.iter().map(|stage| stage.target).fold(start, f64::max)
+    }
+}
+
+impl FromStr for Stages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut stages = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let invalid = |reason: &str| format!("Invalid stage \"{}\": {}. Use duration:target, e.g. 30s:10", part, reason);
+            let (duration, target) = part.split_once(':').ok_or_else(|| invalid("missing ':'"))?;
+            let duration = parse_duration(duration).map_err(|e| invalid(&e))?;
+            let target: f64 = target.trim().parse().map_err(|_| invalid("target is not a number"))?;
+            if duration.is_zero() {
+                return Err(invalid("duration must be greater than zero"));
+            }
+            if !target.is_finite() || target < 0.0 {
+                return Err(invalid("target must be zero or more"));
+            }
+            stages.push(Stage { duration, target });
+        }
+        if stages.is_empty() {
+            return Err("At least one stage is required".to_string());
+        }
+        Ok(Stages(stages))
+    }
+}
+
+impl fmt::Display for Stages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|stage| format!("{}:{}", format_duration(stage.duration), stage.target))
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+/// Everything that shapes the load of a run: how long it lasts, how many
+/// workers run and whether requests follow a rate and/or a staged profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadProfile {
+    pub length: RunLength,
+    pub workers: u32,
+    pub rate: Option<Rate>,
+    /// Targets are workers for closed runs, or requests per second with `rate`
+    pub stages: Option<Stages>,
+}
+
+impl LoadProfile {
+    pub fn new(length: RunLength, workers: u32) -> Self {
+        Self {
+            length,
+            workers,
+            rate: None,
+            stages: None,
+        }
+    }
+
+    /// Stages decide the length of the run when present
+    pub fn run_length(&self) -> RunLength {
+        match &self.stages {
+            Some(stages) => RunLength::Duration(stages.total_duration()),
+            None => self.length,
+        }
+    }
+
+    /// Number of worker threads to start
+    pub fn pool_size(&self) -> u32 {
+        match (&self.stages, self.rate) {
+            (Some(stages), None) => (stages.peak(0.0).ceil() as u32).max(1),
+            _ => self.workers.max(1),
+        }
+    }
+
+    /// Number of workers that should be sending requests at `elapsed`
+    pub fn active_workers(&self, elapsed: Duration) -> u32 {
+        match (&self.stages, self.rate) {
+            (Some(stages), None) => stages.target_at(elapsed, 0.0).ceil() as u32,
+            _ => self.workers,
+        }
+    }
+
+    /// Raises the workers of a rate-based run so that a second's worth of
+    /// requests at the busiest point can be in flight at once, up to 1000
+    pub fn fit_workers_to_rate(&mut self) {
+        if let Some(rate) = self.rate {
+            let peak = self.stages.as_ref().map_or(rate.per_second, |stages| stages.peak(rate.per_second));
+            self.workers = (peak.ceil() as u32).clamp(self.workers, 1000.max(self.workers));
+        }
+    }
+
+    pub fn schedule(&self) -> Option<Schedule> {
+        let rate = self.rate?;
+        let schedule = Schedule::new(rate, self.length);
+        Some(match &self.stages {
+            Some(stages) => schedule.with_stages(stages.clone()),
+            None => schedule,
+        })
+    }
+}
+
+/// Parses durations such as `90`, `500ms`, `30s`, `15m`, `2h` or `1h30m`.
+/// A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid duration \"{}\". Use values like 30s, 15m, 1h30m or 500ms", s);
+    if s.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).map_err(|_| invalid());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(invalid)?;
+        let (number, unit_and_rest) = rest.split_at(number_end);
+        let unit_end = unit_and_rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(unit_and_rest.len());
+        let (unit, remaining) = unit_and_rest.split_at(unit_end);
+
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        let seconds = match unit {
+            "ms" => value / 1000.0,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 3600.0,
+            _ => return Err(invalid()),
+        };
+        total += Duration::try_from_secs_f64(seconds).map_err(|_| invalid())?;
+        rest = remaining;
+    }
+    Ok(total)
+}
+
+/// Formats a duration the way `parse_duration` reads it, e.g. `1h30m` or `2.5s`
+pub fn format_duration(duration: Duration) -> String {
+    let total = duration.as_secs();
+    let millis = duration.subsec_millis();
+    if total == 0 {
+        return if millis == 0 { "0s".to_string() } else { format!("{}ms", millis) };
+    }
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    let mut out = String::new();
+    if hours > 0 {
+        out.push_str(&format!("{}h", hours));
+    }
+    if minutes > 0 {
+        out.push_str(&format!("{}m", minutes));
+    }
+    if seconds > 0 || millis > 0 {
+        if millis > 0 {
+            let fraction = format!("{:03}", millis);
+            out.push_str(&format!("{}.{}s", seconds, fraction.trim_end_matches('0')));
+        } else {
+            out.push_str(&format!("{}s", seconds));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("-5").is_err());
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(Rate::parse("100", Arrival::Constant).unwrap().per_second, 100.0);
+        assert_eq!(Rate::parse("50/s", Arrival::Constant).unwrap().per_second, 50.0);
+        assert_eq!(Rate::parse("600/m", Arrival::Constant).unwrap().per_second, 10.0);
+        assert!(Rate::parse("0", Arrival::Constant).is_err());
+        assert!(Rate::parse("10/x", Arrival::Constant).is_err());
+    }
+
+    #[test]
+    fn constant_schedule_is_evenly_spaced() {
+        let rate = Rate { per_second: 4.0, arrival: Arrival::Constant };
+        let schedule = Schedule::new(rate, RunLength::Duration(Duration::from_secs(1)));
+        let slots: Vec<_> = std::iter::from_fn(|| schedule.next_slot()).collect();
+        assert_eq!(
+            slots,
+            vec![
+                (0, Duration::ZERO),
+                (1, Duration::from_millis(250)),
+                (2, Duration::from_millis(500)),
+                (3, Duration::from_millis(750)),
+            ]
+        );
+    }
+
+    #[test]
+    fn schedule_stops_after_number_of_requests() {
+        let rate = Rate { per_second: 1000.0, arrival: Arrival::Poisson };
+        let schedule = Schedule::new(rate, RunLength::Requests(5));
+        assert_eq!(std::iter::from_fn(|| schedule.next_slot()).count(), 5);
+    }
+
+    #[test]
+    fn parses_stages() {
+        let stages: Stages = "30s:10, 2m:50,30s:0".parse().unwrap();
+        assert_eq!(stages.0.len(), 3);
+        assert_eq!(stages.total_duration(), Duration::from_secs(180));
+        assert_eq!(stages.to_string(), "30s:10,2m:50,30s:0");
+        assert!("30s".parse::<Stages>().is_err());
+        assert!("0s:10".parse::<Stages>().is_err());
+        assert!("10s:-1".parse::<Stages>().is_err());
+    }
+
+    #[test]
+    fn stage_targets_ramp_linearly() {
+        let stages: Stages = "10s:10,10s:10,10s:0".parse().unwrap();
+        assert_eq!(stages.target_at(Duration::from_secs(5), 0.0), 5.0);
+        assert_eq!(stages.target_at(Duration::from_secs(15), 0.0), 10.0);
+        assert_eq!(stages.target_at(Duration::from_secs(25), 0.0), 5.0);
+        assert_eq!(stages.target_at(Duration::from_secs(40), 0.0), 0.0);
+        assert_eq!(stages.stage_at(Duration::from_secs(12)), Some(1));
+        assert_eq!(stages.stage_at(Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn staged_schedule_follows_the_ramp() {
+        let rate = Rate { per_second: 0.0, arrival: Arrival::Constant };
+        let stages: Stages = "1s:0,2s:10,1s:10".parse().unwrap();
+        let schedule = Schedule::new(rate, RunLength::Requests(1)).with_stages(stages);
+        let slots: Vec<_> = std::iter::from_fn(|| schedule.next_slot()).collect();
+        // Nothing during the idle first second, about 10 while ramping up and 10 at full rate
+        assert!(slots[0].1 >= Duration::from_secs(1));
+        assert!((18..=22).contains(&slots.len()), "{} slots", slots.len());
+    }
+
+    #[test]
+    fn formats_durations_back() {
+        for text in ["1h30m", "15m", "2.5s", "500ms", "1h1s"] {
+            assert_eq!(format_duration(parse_duration(text).unwrap()), text);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use crate::events::{Event, Subscriber};
+use crate::outcome::ErrorKind;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const HEADER: &str = "index,worker,start_us,duration_us,status,bytes,error";
+
+/// Writes one CSV row per request. As a `Subscriber` it streams rows while the
+/// run is going, so the file does not depend on results held in memory.
+pub struct SampleWriter<W: Write + Send = BufWriter<File>> {
+    state: Mutex<State<W>>,
+}
+
+/// One row of the CSV
+struct Sample {
+    index: u64,
+    worker: u32,
+    start: Duration,
+    duration: Duration,
+    status: Option<u16>,
+    bytes: u64,
+    error: Option<ErrorKind>,
+}
+
+struct State<W> {
+    writer: W,
+    /// First write error, reported by `finish`
+    error: Option<io::Error>,
+}
+
+impl SampleWriter {
+    /// Creates (or truncates) the CSV file at `path`
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        Self::new(BufWriter::new(file)).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+impl<W: Write + Send> SampleWriter<W> {
+    /// Writes the header row straight away
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Self {
+            state: Mutex::new(State { writer, error: None }),
+        })
+    }
+
+    /// Flushes the rows written so far, reporting the first error if any failed
+    pub fn finish(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = state.error.take() {
+            return Err(format!("Failed to write samples: {}", e));
+        }
+        state.writer.flush().map_err(|e| format!("Failed to write samples: {}", e))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.state.into_inner().unwrap().writer
+    }
+
+    fn write_row(&self, sample: Sample) {
+        let mut state = self.state.lock().unwrap();
+        if state.error.is_some() {
+            return;
+        }
+        let result = writeln!(
+            state.writer,
+            "{},{},{},{},{},{},{}",
+            sample.index,
+            sample.worker,
+            sample.start.as_micros(),
+            sample.duration.as_micros(),
+            sample.status.map(|status| status.to_string()).unwrap_or_default(),
+            sample.bytes,
+            sample.error.map(|kind| kind.as_str()).unwrap_or_default()
+        );
+        if let Err(e) = result {
+            state.error = Some(e);
+        }
+    }
+}
+
+impl<W: Write + Send> Subscriber for SampleWriter<W> {
+    fn on_event(&self, event: &Event) {
+        match event {
+            Event::RequestCompleted { index, worker, start, status, duration, bytes, .. } => self.write_row(Sample {
+                index: *index,
+                worker: *worker,
+                start: *start,
+                duration: *duration,
+                status: Some(*status),
+                bytes: *bytes,
+                error: None,
+            }),
+            Event::RequestFailed { index, worker, start, kind, status, duration, bytes, .. } => self.write_row(Sample {
+                index: *index,
+                worker: *worker,
+                start: *start,
+                duration: *duration,
+                status: *status,
+                bytes: *bytes,
+                error: Some(*kind),
+            }),
+            Event::RunFinished { .. } => {
+                let mut state = self.state.lock().unwrap();
+                if let Err(e) = state.writer.flush() {
+                    state.error.get_or_insert(e);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_written_for_finished_requests() {
+        let samples = SampleWriter::new(Vec::new()).unwrap();
+        samples.on_event(&Event::RequestStarted {
+            index: 0,
+            worker: 1,
+            url: "http://localhost/".to_string(),
+        });
+        samples.on_event(&Event::RequestCompleted {
+            index: 0,
+            worker: 1,
+            url: "http://localhost/".to_string(),
+            start: Duration::from_millis(5),
+            status: 200,
+            duration: Duration::from_micros(1500),
+            bytes: 512,
+            phases: Default::default(),
+        });
+        samples.on_event(&Event::RequestFailed {
+            index: 1,
+            worker: 0,
+            url: "http://localhost/".to_string(),
+            start: Duration::from_millis(6),
+            kind: ErrorKind::Timeout,
+            status: None,
+            duration: Duration::from_secs(10),
+            bytes: 0,
+            message: None,
+        });
+        assert!(samples.finish().is_ok());
+
+        let csv = String::from_utf8(samples.into_inner()).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows, vec![HEADER, "0,1,5000,1500,200,512,", "1,0,6000,10000000,,0,timeout"]);
+    }
+}
@@ -0,0 +1,688 @@
+use crate::crawl::CrawlOptions;
+use crate::endpoint::Endpoint;
+use crate::feeder::{Feeder, Strategy};
+use crate::journey::{self, Source};
+use crate::load_test::LoadTestConfig;
+use crate::output::ReportOutput;
+use crate::profile::{Arrival, Rate, Stages, parse_duration};
+use crate::status::ExpectedStatus;
+use crate::template::Templates;
+use crate::thresholds::Threshold;
+use crate::{Method, build_headers, normalise_url, resolve_payload};
+use http::header::HeaderName;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A complete test plan that can be kept under version control, read from a
+/// TOML or YAML file:
+///
+/// ```toml
+/// thresholds = ["p95<300ms", "error_rate<1%"]
+/// outputs = ["json=report.json"]
+///
+/// [target]
+/// url = "https://example.com/api/orders"
+/// method = "post"
+/// payload = '{"item": 42}'
+/// headers = { "X-Client" = "load-test" }
+///
+/// [load]
+/// duration = "5m"
+/// workers = 20
+/// rate = "50/s"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    /// Threshold expressions such as `p95<300ms`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub thresholds: Vec<String>,
+    /// Report outputs such as `json=report.json`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+    pub target: Target,
+    pub load: Load,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follow_links: Option<FollowLinks>,
+    /// Journey every worker goes through in order, in place of requests to
+    /// the target URL
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<Step>,
+    /// Data files whose rows templates use as `{{name.column}}`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub feeders: Vec<FeederFile>,
+    /// Directory relative feeder and payload paths are taken from: that of
+    /// the file for a loaded scenario, otherwise the working directory
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
+}
+
+/// What to send and how to judge the responses
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Target {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    /// Weighted URLs to send requests to in place of `url`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<WeightedUrl>,
+    /// Do not prepend `https://` to the URL
+    #[serde(skip_serializing_if = "is_false")]
+    pub force_url: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearer: Option<String>,
+    /// `user:password`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<String>,
+    /// Raw JSON or the path of a JSON file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "number_or_string")]
+    pub timeout: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub ignore_ssl: bool,
+}
+
+/// A URL of a traffic mix, e.g. `{ url = "https://example.com/search", weight = 20 }`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeightedUrl {
+    pub url: String,
+    /// Relative to the other weights. Defaults to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+}
+
+/// The load profile, with the same meaning as the CLI flags of the same names
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Load {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "number_or_string")]
+    pub duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workers: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "number_or_string")]
+    pub rate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stages: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FollowLinks {
+    pub max_depth: u32,
+    pub max_pages: usize,
+}
+
+impl Default for FollowLinks {
+    fn default() -> Self {
+        let options = CrawlOptions::default();
+        Self {
+            max_depth: options.max_depth,
+            max_pages: options.max_pages,
+        }
+    }
+}
+
+/// A request of the journey. Values extracted by earlier steps can be used in
+/// the URL, header values and payload as `{{name}}`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Step {
+    pub name: String,
+    /// Absolute, or relative to `target.url`. Empty for the target URL itself.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_status: Option<String>,
+    /// Variables to set from the response, e.g. `token = { json = "/token" }`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extract: BTreeMap<String, Extraction>,
+}
+
+/// A CSV file with a header line naming its columns
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeederFile {
+    /// Defaults to `csv`
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub path: String,
+    /// `sequential` (the default), `random` or `unique`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+}
+
+impl FeederFile {
+    fn build(&self, i: usize, scenario: &Scenario) -> Result<Feeder, String> {
+        let at = |name: &str, e: String| format!("feeders[{}].{}: {}", i, name, e);
+        if self.path.trim().is_empty() {
+            return Err(format!("feeders[{}].path is required", i));
+        }
+        let strategy = match &self.strategy {
+            Some(strategy) => strategy.parse::<Strategy>().map_err(|e| at("strategy", e))?,
+            None => Strategy::default(),
+        };
+        let name = match self.name.trim() {
+            "" => "csv",
+            name => name,
+        };
+        Feeder::from_csv(name, scenario.path(self.path.trim()), strategy).map_err(|e| at("path", e))
+    }
+}
+
+/// Where a step takes a value from. Exactly one of the fields is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Extraction {
+    /// JSON pointer into the body, e.g. `/user/id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json: Option<String>,
+    /// Regex on the body, using its first capture group if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookie: Option<String>,
+}
+
+impl Step {
+    fn build(&self, i: usize, scenario: &Scenario) -> Result<journey::Step, String> {
+        // Prefixes an error with the field of this step it is about
+        let at = |name: &str, e: String| format!("steps[{}].{}: {}", i, name, e);
+        if self.name.trim().is_empty() {
+            return Err(format!("steps[{}].name is required", i));
+        }
+        let mut step = journey::Step::new(self.name.trim(), self.url.trim());
+        if let Some(method) = &self.method {
+            step = step.method(method.parse::<Method>().map_err(|e| at("method", e))?);
+        }
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.trim().as_bytes())
+                .map_err(|_| at("headers", format!("Invalid header name: {}", name.trim())))?;
+            step = step.header(name, value.trim());
+        }
+        if let Some(payload) = &self.payload
+            && let Some(payload) = scenario.resolve_payload(payload).map_err(|e| at("payload", e))?
+        {
+            step = step.payload(payload);
+        }
+        if let Some(expected) = &self.expect_status {
+            step = step.expected_status(expected.parse::<ExpectedStatus>().map_err(|e| at("expect_status", e))?);
+        }
+        for (variable, extraction) in &self.extract {
+            let field = format!("extract.{}", variable);
+            let source = match extraction {
+                Extraction { json: Some(pointer), regex: None, header: None, cookie: None } => {
+                    if !pointer.is_empty() && !pointer.starts_with('/') {
+                        return Err(at(&field, format!("JSON pointers start with /, e.g. /{}", pointer)));
+                    }
+                    Source::JsonPointer(pointer.clone())
+                }
+                Extraction { json: None, regex: Some(pattern), header: None, cookie: None } => {
+                    Source::regex(pattern).map_err(|e| at(&field, e))?
+                }
+                Extraction { json: None, regex: None, header: Some(name), cookie: None } => Source::Header(
+                    HeaderName::from_bytes(name.trim().as_bytes())
+                        .map_err(|_| at(&field, format!("Invalid header name: {}", name.trim())))?,
+                ),
+                Extraction { json: None, regex: None, header: None, cookie: Some(name) } => {
+                    Source::Cookie(name.trim().to_string())
+                }
+                _ => return Err(at(&field, "set exactly one of json, regex, header or cookie".to_string())),
+            };
+            step = step.extract(variable, source);
+        }
+        Ok(step)
+    }
+}
+
+/// File formats a scenario can be stored in, picked by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Ok(Format::Toml),
+            Some("yaml" | "yml") => Ok(Format::Yaml),
+            _ => Err(format!("{}: scenario files must end in .toml, .yaml or .yml", path.display())),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())
+    }
+
+    pub fn from_yaml(text: &str) -> Result<Self, String> {
+        serde_yaml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("Scenarios only hold TOML-compatible values")
+    }
+
+    pub fn to_yaml(&self) -> String {
+        serde_yaml::to_string(self).expect("Scenarios only hold YAML-compatible values")
+    }
+
+    /// Reads a `.toml`, `.yaml` or `.yml` file and builds the test it
+    /// describes, checked as by `validate`. Relative feeder and payload paths
+    /// are taken from the directory of the file, but kept as written so the
+    /// scenario saves back unchanged.
+    pub fn load(path: impl AsRef<Path>) -> Result<(Self, LoadTestConfig), String> {
+        let path = path.as_ref();
+        let format = Format::of(path)?;
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let scenario = match format {
+            Format::Toml => Self::from_toml(&text),
+            Format::Yaml => Self::from_yaml(&text),
+        };
+        let mut scenario = scenario.map_err(|e| format!("{}: {}", path.display(), e))?;
+        scenario.base_dir = path.parent().map(Path::to_path_buf);
+        let config = scenario.checked_config().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok((scenario, config))
+    }
+
+    /// A path from the scenario, relative to `base_dir` if it is relative
+    pub fn path(&self, path: &str) -> PathBuf {
+        match &self.base_dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// Like `resolve_payload`, reading payload files relative to `base_dir`
+    pub fn resolve_payload(&self, payload: &str) -> Result<Option<String>, String> {
+        // Payloads starting with `{` or `[` are JSON rather than paths
+        match payload.trim() {
+            "" => Ok(None),
+            json if json.starts_with(['{', '[']) => resolve_payload(json),
+            path => resolve_payload(&self.path(path).to_string_lossy()),
+        }
+    }
+
+    /// Writes the scenario in the format given by the extension of `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = match Format::of(path)? {
+            Format::Toml => self.to_toml(),
+            Format::Yaml => self.to_yaml(),
+        };
+        std::fs::write(path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Checks every value, including that the payload is valid JSON and that
+    /// templates only use feeders and columns that exist
+    pub fn validate(&self) -> Result<(), String> {
+        self.checked_config().map(|_| ())
+    }
+
+    fn checked_config(&self) -> Result<LoadTestConfig, String> {
+        let config = self.load_test_config()?;
+        Templates::new(&config)?;
+        self.report_outputs()?;
+        Ok(config)
+    }
+
+    pub fn report_outputs(&self) -> Result<Vec<ReportOutput>, String> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .map(|(i, output)| output.parse().map_err(|e| format!("outputs[{}]: {}", i, e)))
+            .collect()
+    }
+
+    /// The steps of the journey, checked for variables used before they are set
+    pub fn journey_steps(&self) -> Result<Vec<journey::Step>, String> {
+        let steps = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| step.build(i, self))
+            .collect::<Result<Vec<_>, _>>()?;
+        journey::check_steps(&steps).map_err(field("steps"))?;
+        Ok(steps)
+    }
+
+    /// Reads the data file of each feeder
+    pub fn data_feeders(&self) -> Result<Vec<Feeder>, String> {
+        self.feeders.iter().enumerate().map(|(i, feeder)| feeder.build(i, self)).collect()
+    }
+
+    /// The test described by the scenario. Errors name the field at fault,
+    /// e.g. `load.duration: Invalid duration "5 mins"`.
+    pub fn load_test_config(&self) -> Result<LoadTestConfig, String> {
+        let target = &self.target;
+        let load = &self.load;
+
+        let mut config = match (target.url.trim(), target.endpoints.as_slice()) {
+            ("", []) => return Err("target.url is required".to_string()),
+            (url, []) => LoadTestConfig::new(normalise_url(url.to_string(), target.force_url)),
+            ("", endpoints) => {
+                let mut config = LoadTestConfig::new("");
+                for (i, endpoint) in endpoints.iter().enumerate() {
+                    if endpoint.url.trim().is_empty() {
+                        return Err(format!("target.endpoints[{}].url is required", i));
+                    }
+                    if endpoint.weight == Some(0) {
+                        return Err(format!("target.endpoints[{}].weight: must be at least 1", i));
+                    }
+                    let url = normalise_url(endpoint.url.trim().to_string(), target.force_url);
+                    config = config.endpoint(Endpoint::new(url, endpoint.weight.unwrap_or(1)));
+                }
+                config
+            }
+            _ => return Err("target: set either url or endpoints, not both".to_string()),
+        };
+        if let Some(method) = &target.method {
+            config = config.method(method.parse().map_err(field("target.method"))?);
+        }
+        let headers: Vec<String> = target.headers.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        config = config.headers(
+            build_headers(headers.iter().map(String::as_str), target.bearer.as_deref(), target.basic_auth.as_deref())
+                .map_err(field("target.headers"))?,
+        );
+        if let Some(payload) = &target.payload
+            && let Some(payload) = self.resolve_payload(payload).map_err(field("target.payload"))?
+        {
+            config = config.payload(payload);
+        }
+        if let Some(expected) = &target.expect_status {
+            config = config.expected_status(expected.parse::<ExpectedStatus>().map_err(field("target.expect_status"))?);
+        }
+        if let Some(timeout) = &target.timeout {
+            let timeout = parse_duration(timeout).map_err(field("target.timeout"))?;
+            if timeout.is_zero() {
+                return Err("target.timeout: must be greater than zero".to_string());
+            }
+            config = config.timeout(timeout);
+        }
+        config = config.ignore_ssl(target.ignore_ssl);
+
+        match (load.requests, &load.duration, &load.stages) {
+            (Some(_), Some(_), _) => return Err("load: set either requests or duration, not both".to_string()),
+            (Some(_), _, Some(_)) | (_, Some(_), Some(_)) => {
+                return Err("load: stages decide the length of the run, so requests and duration cannot be set with them".to_string());
+            }
+            _ => {}
+        }
+        if let Some(requests) = load.requests {
+            config = config.requests(requests);
+        }
+        if let Some(duration) = &load.duration {
+            config = config.duration(parse_duration(duration).map_err(field("load.duration"))?);
+        }
+        if let Some(workers) = load.workers {
+            if workers == 0 {
+                return Err("load.workers: must be at least 1".to_string());
+            }
+            config = config.workers(workers);
+        }
+        let arrival = match &load.arrival {
+            Some(_) if load.rate.is_none() => return Err("load.arrival: only used together with load.rate".to_string()),
+            Some(arrival) => arrival.parse::<Arrival>().map_err(field("load.arrival"))?,
+            None => Arrival::Constant,
+        };
+        if let Some(rate) = &load.rate {
+            config = config.rate(Rate::parse(rate, arrival).map_err(field("load.rate"))?);
+        }
+        if let Some(stages) = &load.stages {
+            config = config.stages(stages.parse::<Stages>().map_err(field("load.stages"))?);
+        }
+
+        if !self.steps.is_empty() {
+            if target.method.is_some() || target.payload.is_some() {
+                return Err("target: the method and payload come from each step when there are steps".to_string());
+            }
+            if self.follow_links.is_some() {
+                return Err("steps: cannot be combined with follow_links".to_string());
+            }
+            if !target.endpoints.is_empty() {
+                return Err("steps: cannot be combined with target.endpoints".to_string());
+            }
+            config.steps = self.journey_steps()?;
+        }
+        if let Some(follow_links) = &self.follow_links {
+            if !target.endpoints.is_empty() {
+                return Err("follow_links: cannot be combined with target.endpoints".to_string());
+            }
+            config = config.follow_links(CrawlOptions {
+                max_depth: follow_links.max_depth,
+                max_pages: follow_links.max_pages,
+            });
+        }
+        for feeder in self.data_feeders()? {
+            config = config.feeder(feeder);
+        }
+        for (i, threshold) in self.thresholds.iter().enumerate() {
+            config = config.threshold(threshold.parse::<Threshold>().map_err(|e| format!("thresholds[{}]: {}", i, e))?);
+        }
+        Ok(config)
+    }
+}
+
+/// Prefixes an error with the name of the field it is about
+fn field(name: &'static str) -> impl Fn(String) -> String {
+    move |e| format!("{}: {}", name, e)
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Lets durations and rates be written as a bare number as well as a string
+/// such as `30s` or `50/s`
+fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Text(String),
+    }
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(number) => Some(number.to_string()),
+        Value::Text(text) => Some(text),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Method;
+    use crate::profile::RunLength;
+    use std::time::Duration;
+
+    const TOML: &str = r#"
+thresholds = ["p95<300ms"]
+outputs = ["json=report.json"]
+
+[target]
+url = "http://localhost:8080/orders"
+method = "post"
+payload = '{"item": 42}'
+headers = { "X-Client" = "load-test" }
+timeout = 2.5
+
+[load]
+duration = "5m"
+rate = 50
+arrival = "poisson"
+"#;
+
+    #[test]
+    fn toml_scenarios_are_turned_into_configs() {
+        let scenario = Scenario::from_toml(TOML).unwrap();
+        let config = scenario.load_test_config().unwrap();
+        assert_eq!(config.method, Method::Post);
+        assert_eq!(config.payload.as_deref(), Some(r#"{"item": 42}"#));
+        assert_eq!(config.headers["x-client"], "load-test");
+        assert_eq!(config.timeout, Duration::from_millis(2500));
+        assert_eq!(config.profile.length, RunLength::Duration(Duration::from_secs(300)));
+        assert_eq!(config.profile.rate.unwrap().arrival, Arrival::Poisson);
+        // Workers default to a second's worth of requests at the rate
+        assert_eq!(config.profile.workers, 50);
+        assert_eq!(config.thresholds.len(), 1);
+        assert_eq!(scenario.report_outputs().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn scenarios_round_trip_through_both_formats() {
+        let scenario = Scenario::from_toml(TOML).unwrap();
+        assert_eq!(Scenario::from_toml(&scenario.to_toml()).unwrap(), scenario);
+        assert_eq!(Scenario::from_yaml(&scenario.to_yaml()).unwrap(), scenario);
+
+        let yaml = "target:\n  url: http://localhost/\nload:\n  requests: 10\nfollow_links:\n  max_depth: 2\n";
+        let config = Scenario::from_yaml(yaml).unwrap().load_test_config().unwrap();
+        assert_eq!(config.profile.length, RunLength::Requests(10));
+        assert_eq!(config.crawl.unwrap().max_pages, CrawlOptions::default().max_pages);
+    }
+
+    #[test]
+    fn steps_become_a_journey() {
+        let toml = r#"
+[target]
+url = "http://localhost:8080/"
+
+[[steps]]
+name = "login"
+url = "/login"
+method = "post"
+payload = '{"user": "a"}'
+extract = { token = { json = "/token" }, session = { cookie = "session" } }
+
+[[steps]]
+name = "order"
+url = "/orders"
+method = "post"
+headers = { Authorization = "Bearer {{token}}", Cookie = "session={{session}}" }
+payload = '{"item": {{item}}}'
+"#;
+        let scenario = Scenario::from_toml(toml).unwrap();
+        assert_eq!(scenario.validate().unwrap_err(), "steps: Step \"order\" uses {{item}}, which no earlier step extracts");
+
+        let scenario = Scenario::from_toml(&toml.replace("{{item}}", "7")).unwrap();
+        let config = scenario.load_test_config().unwrap();
+        assert_eq!(config.steps.len(), 2);
+        assert_eq!(config.steps[0].extract.len(), 2);
+        assert_eq!(config.steps[1].headers.len(), 2);
+        assert_eq!(Scenario::from_toml(&scenario.to_toml()).unwrap(), scenario);
+        assert_eq!(Scenario::from_yaml(&scenario.to_yaml()).unwrap(), scenario);
+
+        let error = |toml: &str| Scenario::from_toml(toml).and_then(|scenario| scenario.validate()).unwrap_err();
+        let base = "[target]\nurl = \"http://localhost/\"\n[[steps]]\nname = \"a\"\n";
+        assert!(error(&format!("{}extract = {{ t = {{ json = \"token\" }} }}", base)).starts_with("steps[0].extract.t: "));
+        assert!(error(&format!("{}extract = {{ t = {{ xpath = \"//a\" }} }}", base)).contains("unknown field `xpath`"));
+        assert!(error(&format!("{}extract = {{ t = {{ json = \"/a\", cookie = \"b\" }} }}", base)).contains("exactly one"));
+        assert!(error(&format!("{}method = \"gett\"", base)).starts_with("steps[0].method: "));
+        assert!(error(&base.replace("url = ", "method = \"post\"\nurl = ")).starts_with("target: "));
+    }
+
+    #[test]
+    fn endpoints_make_a_traffic_mix() {
+        let toml = r#"
+[target]
+endpoints = [
+    { url = "example.com/", weight = 70 },
+    { url = "example.com/search?q={{randInt 1 100}}", weight = 30 },
+    { url = "example.com/checkout" },
+]
+"#;
+        let scenario = Scenario::from_toml(toml).unwrap();
+        scenario.validate().unwrap();
+        let config = scenario.load_test_config().unwrap();
+        assert_eq!(config.url, "https://example.com/");
+        let weights: Vec<u32> = config.endpoints.iter().map(|endpoint| endpoint.weight).collect();
+        assert_eq!(weights, vec![70, 30, 1]);
+        assert_eq!(Scenario::from_yaml(&scenario.to_yaml()).unwrap(), scenario);
+
+        let error = |toml: &str| Scenario::from_toml(toml).and_then(|scenario| scenario.validate()).unwrap_err();
+        assert!(error(&toml.replace("[target]", "[target]\nurl = \"example.com\"")).starts_with("target: "));
+        assert!(error(&toml.replace("weight = 30", "weight = 0")).starts_with("target.endpoints[1].weight: "));
+        assert!(error(&format!("{}[follow_links]\n", toml)).starts_with("follow_links: "));
+    }
+
+    #[test]
+    fn feeders_are_read_and_checked() {
+        let path = std::env::temp_dir().join(format!("site_tester_feeder_{}.csv", std::process::id()));
+        std::fs::write(&path, "user_id,name\n1,Ann\n2,Bo\n").unwrap();
+        let toml = format!(
+            "[target]\nurl = \"http://localhost/users/{{{{users.user_id}}}}\"\n[[feeders]]\nname = \"users\"\npath = '{}'\nstrategy = \"random\"\n",
+            path.display()
+        );
+        let scenario = Scenario::from_toml(&toml).unwrap();
+        scenario.validate().unwrap();
+        let config = scenario.load_test_config().unwrap();
+        assert_eq!(config.feeders[0].len(), 2);
+        assert_eq!(Scenario::from_toml(&scenario.to_toml()).unwrap(), scenario);
+
+        let error = |toml: &str| Scenario::from_toml(toml).and_then(|scenario| scenario.validate()).unwrap_err();
+        assert!(error(&toml.replace("users.user_id", "users.email")).contains("no column email"));
+        assert!(error(&toml.replace("\"random\"", "\"shuffled\"")).starts_with("feeders[0].strategy: "));
+        assert!(error(&toml.replace(".csv", ".missing")).starts_with("feeders[0].path: Failed to read"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn data_files_are_found_next_to_the_scenario() {
+        // A relative directory, as scenarios are usually given on the command line
+        let dir = PathBuf::from(format!("target/site_tester_scenario_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        std::fs::write(dir.join("data/users.csv"), "user_id\n1\n").unwrap();
+        std::fs::write(dir.join("data/body.json"), "{\"user\": \"{{users.user_id}}\"}").unwrap();
+        std::fs::write(
+            dir.join("plan.toml"),
+            "[target]\nurl = \"http://localhost/\"\nmethod = \"post\"\npayload = \"data/body.json\"\n\
+            [[feeders]]\nname = \"users\"\npath = \"data/users.csv\"\n",
+        )
+        .unwrap();
+
+        let (scenario, config) = Scenario::load(dir.join("plan.toml")).unwrap();
+        assert_eq!(scenario.feeders[0].path, "data/users.csv");
+        assert_eq!(scenario.target.payload.as_deref(), Some("data/body.json"));
+        assert_eq!(config.feeders[0].len(), 1);
+        assert_eq!(config.payload.as_deref(), Some("{\"user\": \"{{users.user_id}}\"}"));
+
+        // Saving writes the paths back as they were, so loading again finds the same files
+        scenario.save(dir.join("plan.toml")).unwrap();
+        let (reloaded, config) = Scenario::load(dir.join("plan.toml")).unwrap();
+        assert_eq!(reloaded, scenario);
+        assert_eq!(config.feeders[0].len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mistakes_are_reported_with_the_field() {
+        let error = |toml: &str| Scenario::from_toml(toml).and_then(|scenario| scenario.validate()).unwrap_err();
+        assert!(error("[target]\nurl = \"http://localhost/\"\nmethd = \"get\"").contains("unknown field `methd`"));
+        assert_eq!(error("[load]\nrequests = 5"), "target.url is required");
+        assert!(error("[target]\nurl = \"http://localhost/\"\n[load]\nduration = \"5 mins\"").starts_with("load.duration: "));
+        assert!(error("[target]\nurl = \"http://localhost/\"\n[load]\nrequests = 5\nduration = \"1m\"").starts_with("load: "));
+        assert!(error("[target]\nurl = \"http://localhost/\"\n[load]\narrival = \"poisson\"").starts_with("load.arrival: "));
+        assert!(error("thresholds = [\"p95<300\"]\n[target]\nurl = \"http://localhost/\"").starts_with("thresholds[0]: "));
+        assert!(error("outputs = [\"pdf\"]\n[target]\nurl = \"http://localhost/\"").starts_with("outputs[0]: "));
+    }
+}
@@ -0,0 +1,567 @@
+use crate::outcome::{ErrorKind, Phase, RequestOutcome};
+use crate::profile::Rate;
+use hdrhistogram::Histogram;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// A rate-based request starting later than this after its slot means the
+/// generator is not keeping up with the target rate
+pub const LATE_START: Duration = Duration::from_millis(10);
+
+/// Percentiles shown in every summary
+pub const PERCENTILES: [f64; 5] = [50.0, 90.0, 95.0, 99.0, 99.9];
+
+/// Latency statistics for a run, kept in an HDR histogram so memory use does not
+/// grow with the number of requests. Values are recorded in microseconds with
+/// three significant figures of precision. The histogram resizes itself, so any
+/// latency a `Duration` can hold is tracked.
+///
+/// Rate-based runs also keep a corrected histogram, timed from each request's
+/// slot rather than its actual send, to account for coordinated omission.
+/// Each phase of successful requests gets a histogram of its own.
+#[derive(Clone)]
+pub struct Stats {
+    histogram: Histogram<u64>,
+    corrected: Histogram<u64>,
+    phases: Vec<Histogram<u64>>,
+    fails: u64,
+    status_codes: BTreeMap<u16, u64>,
+    errors: BTreeMap<ErrorKind, u64>,
+    first_start: Option<Duration>,
+    last_start: Duration,
+    scheduled: u64,
+    late_starts: u64,
+    max_lag: Duration,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            histogram: Histogram::new(3).expect("Valid histogram precision"),
+            corrected: Histogram::new(3).expect("Valid histogram precision"),
+            phases: Phase::ALL
+                .iter()
+                .map(|_| Histogram::new(3).expect("Valid histogram precision"))
+                .collect(),
+            fails: 0,
+            status_codes: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            first_start: None,
+            last_start: Duration::ZERO,
+            scheduled: 0,
+            late_starts: 0,
+            max_lag: Duration::ZERO,
+        }
+    }
+
+    pub fn from_outcomes<'a, I>(outcomes: I) -> Self
+    where
+        I: IntoIterator<Item = &'a RequestOutcome>,
+    {
+        let mut stats = Self::new();
+        for outcome in outcomes {
+            stats.record_outcome(outcome);
+        }
+        stats
+    }
+
+    pub fn record_outcome(&mut self, outcome: &RequestOutcome) {
+        self.first_start = Some(self.first_start.map_or(outcome.start, |first| first.min(outcome.start)));
+        self.last_start = self.last_start.max(outcome.start);
+        if let Some(scheduled) = outcome.scheduled {
+            let lag = outcome.start.saturating_sub(scheduled);
+            let lag = Duration::from_micros(lag.as_micros() as u64);
+            self.scheduled += 1;
+            self.max_lag = self.max_lag.max(lag);
+            if lag > LATE_START {
+                self.late_starts += 1;
+            }
+        }
+        if let Some(status) = outcome.status {
+            *self.status_codes.entry(status).or_default() += 1;
+        }
+        match outcome.error {
+            None => {
+                self.record(outcome.duration);
+                if let Some(corrected) = outcome.corrected_duration() {
+                    record_micros(&mut self.corrected, corrected);
+                }
+                for phase in Phase::ALL {
+                    if let Some(duration) = outcome.phases.get(phase) {
+                        record_micros(&mut self.phases[phase as usize], duration);
+                    }
+                }
+            }
+            Some(error) => {
+                *self.errors.entry(error).or_default() += 1;
+                self.record_failure();
+            }
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        record_micros(&mut self.histogram, duration);
+    }
+
+    pub fn record_failure(&mut self) {
+        self.fails += 1;
+    }
+
+    pub fn successes(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn fails(&self) -> u64 {
+        self.fails
+    }
+
+    /// Responses that arrived with a status code outside the expected ones
+    pub fn http_errors(&self) -> u64 {
+        self.errors.get(&ErrorKind::Status).copied().unwrap_or(0)
+    }
+
+    /// Requests that failed without a usable response
+    pub fn transport_errors(&self) -> u64 {
+        self.errors
+            .iter()
+            .filter(|(kind, _)| kind.is_transport())
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Number of responses seen for each status code, including unexpected ones
+    pub fn status_codes(&self) -> &BTreeMap<u16, u64> {
+        &self.status_codes
+    }
+
+    /// Number of failures of each kind
+    pub fn errors(&self) -> &BTreeMap<ErrorKind, u64> {
+        &self.errors
+    }
+
+    /// Requests started per second between the first and last request
+    pub fn throughput(&self) -> f64 {
+        let span = self.last_start.saturating_sub(self.first_start.unwrap_or_default());
+        if self.total() < 2 || span.is_zero() {
+            return 0.0;
+        }
+        (self.total() - 1) as f64 / span.as_secs_f64()
+    }
+
+    /// Rate-based requests that started more than `LATE_START` after their slot
+    pub fn late_starts(&self) -> u64 {
+        self.late_starts
+    }
+
+    /// Longest delay between a request's slot and when it was actually sent
+    pub fn max_lag(&self) -> Duration {
+        self.max_lag
+    }
+
+    pub fn total(&self) -> u64 {
+        self.successes() + self.fails
+    }
+
+    pub fn min(&self) -> Duration {
+        Duration::from_micros(self.histogram.min())
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.histogram.max())
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_micros(self.histogram.mean().round() as u64)
+    }
+
+    pub fn stddev(&self) -> Duration {
+        Duration::from_micros(self.histogram.stdev().round() as u64)
+    }
+
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.histogram.value_at_percentile(percentile))
+    }
+
+    /// Number of successful requests that went through `phase`. Connection
+    /// setup phases are skipped by requests that reuse a connection.
+    pub fn phase_count(&self, phase: Phase) -> u64 {
+        self.phases[phase as usize].len()
+    }
+
+    pub fn phase_mean(&self, phase: Phase) -> Duration {
+        Duration::from_micros(self.phases[phase as usize].mean().round() as u64)
+    }
+
+    pub fn phase_percentile(&self, phase: Phase, percentile: f64) -> Duration {
+        Duration::from_micros(self.phases[phase as usize].value_at_percentile(percentile))
+    }
+
+    /// Whether coordinated-omission-corrected latencies were recorded
+    pub fn has_corrected(&self) -> bool {
+        !self.corrected.is_empty()
+    }
+
+    pub fn corrected_mean(&self) -> Duration {
+        Duration::from_micros(self.corrected.mean().round() as u64)
+    }
+
+    pub fn corrected_max(&self) -> Duration {
+        Duration::from_micros(self.corrected.max())
+    }
+
+    /// Percentile of latency measured from each request's intended send time
+    pub fn corrected_percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.corrected.value_at_percentile(percentile))
+    }
+
+    /// Splits the range between the fastest and slowest request into `buckets`
+    /// equal slices and counts the requests in each one.
+    pub fn buckets(&self, buckets: u64) -> Vec<(Duration, Duration, u64)> {
+        if self.histogram.is_empty() || buckets == 0 {
+            return Vec::new();
+        }
+        let low = self.histogram.min();
+        let high = self.histogram.max();
+        let width = ((high - low) / buckets).max(1);
+        let buckets = ((high - low) / width + 1).min(buckets);
+
+        let mut counts = vec![0; buckets as usize];
+        for value in self.histogram.iter_recorded() {
+            let bucket = ((value.value_iterated_to() - low) / width).min(buckets - 1);
+            counts[bucket as usize] += value.count_at_value();
+        }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let start = low + i as u64 * width;
+                let end = if i as u64 == buckets - 1 { high } else { start + width - 1 };
+                (Duration::from_micros(start), Duration::from_micros(end), count)
+            })
+            .collect()
+    }
+
+    /// Label and value pairs describing the run, shared by the CLI and GUI output
+    pub fn summary(&self) -> Vec<(String, String)> {
+        let mut lines = vec![
+            ("Completed requests".to_string(), self.total().to_string()),
+            ("Failed requests".to_string(), self.fails.to_string()),
+        ];
+        if self.total() > 1 {
+            lines.push(("Throughput".to_string(), format!("{:.1} requests/s", self.throughput())));
+        }
+        if self.scheduled > 0 {
+            lines.push(("Late starts".to_string(), self.late_starts.to_string()));
+            lines.push(("Max start lag".to_string(), format!("{:?}", self.max_lag)));
+        }
+        if self.fails > 0 {
+            lines.push(("HTTP errors".to_string(), self.http_errors().to_string()));
+            lines.push(("Transport errors".to_string(), self.transport_errors().to_string()));
+            for (kind, count) in self.errors.iter().filter(|(kind, _)| kind.is_transport()) {
+                lines.push((format!("  {} errors", kind), count.to_string()));
+            }
+            if let Some(count) = self.errors.get(&ErrorKind::Extract) {
+                lines.push(("Extraction errors".to_string(), count.to_string()));
+            }
+        }
+        if self.successes() == 0 {
+            return lines;
+        }
+
+        lines.push(("Average time".to_string(), format!("{:?}", self.mean())));
+        lines.push(("Standard deviation".to_string(), format!("{:?}", self.stddev())));
+        lines.push(("Minimum time".to_string(), format!("{:?}", self.min())));
+        for percentile in PERCENTILES {
+            lines.push((format!("p{}", percentile), format!("{:?}", self.percentile(percentile))));
+        }
+        lines.push(("Maximum time".to_string(), format!("{:?}", self.max())));
+        if self.has_corrected() {
+            lines.push(("Corrected average time".to_string(), format!("{:?}", self.corrected_mean())));
+            for percentile in PERCENTILES {
+                lines.push((
+                    format!("Corrected p{}", percentile),
+                    format!("{:?}", self.corrected_percentile(percentile)),
+                ));
+            }
+            lines.push(("Corrected maximum time".to_string(), format!("{:?}", self.corrected_max())));
+        }
+        for phase in Phase::ALL.into_iter().filter(|&phase| self.phase_count(phase) > 0) {
+            let mut value = format!(
+                "average {:?}, p95 {:?}",
+                self.phase_mean(phase),
+                self.phase_percentile(phase, 95.0)
+            );
+            if phase.is_connection_setup() {
+                value.push_str(&format!(" over {} connections", self.phase_count(phase)));
+            }
+            lines.push((phase.label().to_string(), value));
+        }
+        lines
+    }
+
+    /// Explains that the generator fell behind the target rate, if it did
+    pub fn rate_warning(&self, rate: &Rate) -> Option<String> {
+        if self.late_starts == 0 {
+            return None;
+        }
+        Some(format!(
+            "Could not sustain the target rate of {:.1} requests/s: {} requests started more than {:?} \
+            late (worst {:?}), achieved {:.1} requests/s. Try more workers.",
+            rate.per_second,
+            self.late_starts,
+            LATE_START,
+            self.max_lag,
+            self.throughput()
+        ))
+    }
+
+    /// One line per status code seen, with its share of all responses
+    pub fn status_lines(&self) -> Vec<String> {
+        let responses: u64 = self.status_codes.values().sum();
+        self.status_codes
+            .iter()
+            .map(|(status, count)| {
+                let share = *count as f64 * 100.0 / responses as f64;
+                format!("{}: {} ({:.1}%)", status, count, share)
+            })
+            .collect()
+    }
+
+    /// Text bar chart of the latency distribution, one line per bucket
+    pub fn histogram_lines(&self, buckets: u64, bar_width: usize) -> Vec<String> {
+        let buckets = self.buckets(buckets);
+        let largest = buckets.iter().map(|&(_, _, count)| count).max().unwrap_or(0).max(1);
+
+        buckets
+            .into_iter()
+            .map(|(start, end, count)| {
+                let bar = "#".repeat((count as usize * bar_width).div_ceil(largest as usize));
+                format!("{:>10?} - {:<10?} | {:<bar_width$} {}", start, end, bar, count)
+            })
+            .collect()
+    }
+}
+
+/// Requests started in one second of a run, small enough to keep one for every
+/// second of a long run. Latencies go into a single histogram with one
+/// significant figure, which is plenty for a chart, next to an exact mean and max.
+#[derive(Clone)]
+pub struct SecondStats {
+    histogram: Histogram<u64>,
+    fails: u64,
+    /// Sum of the latencies of successful requests in microseconds
+    sum: u64,
+    max: Duration,
+}
+
+impl Default for SecondStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecondStats {
+    pub fn new() -> Self {
+        Self {
+            histogram: Histogram::new(1).expect("Valid histogram precision"),
+            fails: 0,
+            sum: 0,
+            max: Duration::ZERO,
+        }
+    }
+
+    pub fn record_outcome(&mut self, outcome: &RequestOutcome) {
+        if outcome.is_success() {
+            record_micros(&mut self.histogram, outcome.duration);
+            self.sum = self.sum.saturating_add(u64::try_from(outcome.duration.as_micros()).unwrap_or(u64::MAX));
+            self.max = self.max.max(outcome.duration);
+        } else {
+            self.fails += 1;
+        }
+    }
+
+    pub fn successes(&self) -> u64 {
+        self.histogram.len()
+    }
+
+    pub fn fails(&self) -> u64 {
+        self.fails
+    }
+
+    pub fn total(&self) -> u64 {
+        self.successes() + self.fails
+    }
+
+    pub fn mean(&self) -> Duration {
+        Duration::from_micros(self.sum.checked_div(self.successes()).unwrap_or(0))
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Percentile of latency, to within about 10%
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.histogram.value_at_percentile(percentile)).min(self.max)
+    }
+}
+
+fn record_micros(histogram: &mut Histogram<u64>, duration: Duration) {
+    let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX).max(1);
+    if histogram.record(micros).is_err() {
+        histogram.saturating_record(micros);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_latencies_do_not_overflow() {
+        let mut stats = Stats::new();
+        // 10k requests of 30 seconds each is far beyond what a u32 of microseconds holds
+        for _ in 0..10_000 {
+            stats.record(Duration::from_secs(30));
+        }
+        assert_eq!(stats.successes(), 10_000);
+        assert_eq!(stats.mean().as_secs(), 30);
+        assert_eq!(stats.max().as_secs(), 30);
+    }
+
+    #[test]
+    fn latencies_over_an_hour_are_tracked() {
+        let mut stats = Stats::new();
+        stats.record(Duration::from_millis(5));
+        stats.record(Duration::from_secs(2 * 60 * 60));
+        assert_eq!(stats.max().as_secs() / 60, 120);
+        assert_eq!(stats.min().as_millis(), 5);
+    }
+
+    #[test]
+    fn sub_microsecond_latency_counts_as_a_success() {
+        let mut stats = Stats::new();
+        stats.record(Duration::from_nanos(10));
+        stats.record_failure();
+        assert_eq!(stats.successes(), 1);
+        assert_eq!(stats.fails(), 1);
+        assert_eq!(stats.total(), 2);
+    }
+
+    #[test]
+    fn corrected_latency_includes_time_behind_schedule() {
+        let outcome = |scheduled_ms, start_ms| RequestOutcome {
+            index: 0,
+            worker: 0,
+            url: String::new(),
+            step: None,
+            endpoint: None,
+            start: Duration::from_millis(start_ms),
+            scheduled: Some(Duration::from_millis(scheduled_ms)),
+            duration: Duration::from_millis(10),
+            status: Some(200),
+            bytes: 0,
+            error: None,
+            phases: Default::default(),
+        };
+        // The second request was held up 500ms behind a stalled one
+        let stats = Stats::from_outcomes(&[outcome(0, 0), outcome(10, 510)]);
+        assert_eq!(stats.max().as_millis(), 10);
+        assert_eq!(stats.corrected_max().as_millis(), 510);
+        assert!(stats.summary().iter().any(|(label, _)| label == "Corrected p99"));
+    }
+
+    #[test]
+    fn seconds_keep_exact_means_and_rough_percentiles() {
+        let outcome = |millis, error| RequestOutcome {
+            index: 0,
+            worker: 0,
+            url: String::new(),
+            step: None,
+            endpoint: None,
+            start: Duration::ZERO,
+            scheduled: None,
+            duration: Duration::from_millis(millis),
+            status: Some(200),
+            bytes: 0,
+            error,
+            phases: Default::default(),
+        };
+        let mut second = SecondStats::new();
+        for millis in 1..=100 {
+            second.record_outcome(&outcome(millis, None));
+        }
+        second.record_outcome(&outcome(5_000, Some(ErrorKind::Timeout)));
+        assert_eq!((second.total(), second.fails()), (101, 1));
+        assert_eq!(second.mean(), Duration::from_micros(50_500));
+        assert_eq!(second.max(), Duration::from_millis(100));
+        let p95 = second.percentile(95.0).as_secs_f64() * 1000.0;
+        assert!((85.0..=105.0).contains(&p95), "{}", p95);
+        assert_eq!(SecondStats::new().mean(), Duration::ZERO);
+    }
+
+    #[test]
+    fn percentiles_and_spread_match_a_known_distribution() {
+        // Values below 2048µs are stored exactly at three significant figures
+        let mut stats = Stats::new();
+        for micros in 1..=1000 {
+            stats.record(Duration::from_micros(micros));
+        }
+        assert_eq!(stats.min(), Duration::from_micros(1));
+        assert_eq!(stats.max(), Duration::from_micros(1000));
+        assert_eq!(stats.percentile(50.0), Duration::from_micros(500));
+        assert_eq!(stats.percentile(95.0), Duration::from_micros(950));
+        assert_eq!(stats.percentile(99.0), Duration::from_micros(990));
+        assert_eq!(stats.mean(), Duration::from_micros(501));
+        // Standard deviation of 1..=1000 is sqrt((1000² - 1) / 12) ≈ 288.7
+        assert_eq!(stats.stddev(), Duration::from_micros(289));
+
+        // Larger values are kept to within 0.1%
+        let mut stats = Stats::new();
+        for millis in 1..=1000 {
+            stats.record(Duration::from_millis(millis));
+        }
+        let close = |actual: Duration, millis: f64| (actual.as_secs_f64() * 1000.0 - millis).abs() <= millis / 1000.0;
+        for (percentile, millis) in [(50.0, 500.0), (95.0, 950.0), (99.0, 990.0), (100.0, 1000.0)] {
+            assert!(close(stats.percentile(percentile), millis), "p{}: {:?}", percentile, stats.percentile(percentile));
+        }
+        assert!(close(stats.min(), 1.0) && close(stats.max(), 1000.0));
+    }
+
+    #[test]
+    fn buckets_split_the_range_evenly() {
+        let mut stats = Stats::new();
+        for micros in 1..=1000 {
+            stats.record(Duration::from_micros(micros));
+        }
+        let buckets = stats.buckets(10);
+        assert_eq!(buckets.len(), 10);
+        // 999µs between the fastest and slowest makes buckets 99µs wide, the last one taking the rest
+        for (i, &(start, end, count)) in buckets.iter().enumerate().take(9) {
+            let start_micros = 1 + 99 * i as u64;
+            assert_eq!((start, end, count), (Duration::from_micros(start_micros), Duration::from_micros(start_micros + 98), 99));
+        }
+        assert_eq!(buckets[9], (Duration::from_micros(892), Duration::from_micros(1000), 109));
+        assert_eq!(buckets.iter().map(|(_, _, count)| count).sum::<u64>(), 1000);
+
+        let mut stats = Stats::new();
+        stats.record(Duration::from_micros(5));
+        stats.record(Duration::from_micros(7));
+        assert_eq!(stats.buckets(10).len(), 3);
+        assert_eq!(stats.buckets(10)[2], (Duration::from_micros(7), Duration::from_micros(7), 1));
+    }
+
+    #[test]
+    fn empty_stats_report_zero() {
+        let stats = Stats::new();
+        assert_eq!(stats.mean(), Duration::ZERO);
+        assert!(stats.buckets(10).is_empty());
+    }
+}
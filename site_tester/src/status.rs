@@ -0,0 +1,112 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Status codes that count as a successful response.
+/// Parsed from a comma separated list such as `2xx,3xx,404` or `200-299`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedStatus {
+    ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl Default for ExpectedStatus {
+    fn default() -> Self {
+        Self {
+            ranges: vec![200..=299, 300..=399],
+        }
+    }
+}
+
+impl ExpectedStatus {
+    pub fn matches(&self, status: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&status))
+    }
+}
+
+impl FromStr for ExpectedStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let invalid = || format!("Invalid expected status \"{}\". Use codes like 200, ranges like 200-299 or classes like 2xx", part);
+            let range = if let Some(class) = part.strip_suffix("xx").or_else(|| part.strip_suffix("XX")) {
+                let class: u16 = class.parse().map_err(|_| invalid())?;
+                if !(1..=5).contains(&class) {
+                    return Err(invalid());
+                }
+                class * 100..=class * 100 + 99
+            } else if let Some((start, end)) = part.split_once('-') {
+                let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+                let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+                start..=end
+            } else {
+                let code: u16 = part.parse().map_err(|_| invalid())?;
+                code..=code
+            };
+            if range.is_empty() || *range.start() < 100 || *range.end() > 599 {
+                return Err(invalid());
+            }
+            ranges.push(range);
+        }
+
+        if ranges.is_empty() {
+            return Err("At least one expected status is required".to_string());
+        }
+        Ok(Self { ranges })
+    }
+}
+
+impl fmt::Display for ExpectedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|range| {
+                let (start, end) = (*range.start(), *range.end());
+                if start == end {
+                    start.to_string()
+                } else if start % 100 == 0 && end == start + 99 {
+                    format!("{}xx", start / 100)
+                } else {
+                    format!("{}-{}", start, end)
+                }
+            })
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_accepts_2xx_and_3xx() {
+        let expected = ExpectedStatus::default();
+        assert!(expected.matches(200));
+        assert!(expected.matches(304));
+        assert!(!expected.matches(404));
+        assert!(!expected.matches(503));
+    }
+
+    #[test]
+    fn parses_codes_ranges_and_classes() {
+        let expected: ExpectedStatus = "2xx, 404,500-502".parse().unwrap();
+        assert!(expected.matches(204));
+        assert!(expected.matches(404));
+        assert!(expected.matches(501));
+        assert!(!expected.matches(503));
+        assert_eq!(expected.to_string(), "2xx,404,500-502");
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!("".parse::<ExpectedStatus>().is_err());
+        assert!("abc".parse::<ExpectedStatus>().is_err());
+        assert!("299-200".parse::<ExpectedStatus>().is_err());
+        assert!("7xx".parse::<ExpectedStatus>().is_err());
+        assert!("700xx".parse::<ExpectedStatus>().is_err());
+        assert!("9999xx".parse::<ExpectedStatus>().is_err());
+    }
+}
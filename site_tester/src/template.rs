@@ -0,0 +1,517 @@
+use crate::endpoint::{Endpoint, Mix};
+use crate::feeder::{Feeder, Strategy};
+use crate::journey::Step;
+use crate::load_test::LoadTestConfig;
+use crate::output::format_timestamp;
+use crate::set_json_content_type;
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Text with `{{...}}` placeholders that are filled in for every request:
+///
+/// - `{{uuid}}`: a random UUID
+/// - `{{seq}}`: the index of the request in the run
+/// - `{{randInt 1 1000}}`: a random whole number between the two, inclusive
+/// - `{{timestamp}}`: Unix time in seconds, `{{now}}` the time in RFC 3339
+/// - `{{csv.user_id}}`: a column of the current row of a feeder
+/// - `{{token}}`: a value extracted by an earlier journey step
+///
+/// A `{{` without a closing `}}` is kept as it is. In a payload, feeder and
+/// step values are JSON-escaped, so they can be put inside a JSON string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Uuid,
+    Seq,
+    RandInt(i64, i64),
+    Timestamp,
+    Now,
+    Column { feeder: String, column: String },
+    Variable(String),
+}
+
+/// What templates are filled in with for one request
+pub(crate) struct Scope<'a> {
+    pub index: u64,
+    pub worker: u32,
+    pub workers: u32,
+    pub feeders: &'a [Arc<Feeder>],
+    pub variables: Option<&'a HashMap<String, String>>,
+    /// Row taken from each feeder, so every column used together comes from one row
+    pub rows: &'a mut HashMap<usize, usize>,
+}
+
+impl Template {
+    /// Whether there is nothing to fill in
+    pub fn is_static(&self) -> bool {
+        self.parts.iter().all(|part| matches!(part, Part::Text(_)))
+    }
+
+    /// Names of the journey variables the template uses
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Variable(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The feeder and column of each `{{feeder.column}}`
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Column { feeder, column } => Some((feeder.as_str(), column.as_str())),
+            _ => None,
+        })
+    }
+
+    /// The text with every placeholder replaced by `0`, to check what is around them
+    pub fn skeleton(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                _ => "0",
+            })
+            .collect()
+    }
+
+    pub(crate) fn render(&self, scope: &mut Scope) -> Result<String, String> {
+        self.fill(scope, false)
+    }
+
+    /// Renders a JSON payload, escaping the values of feeders and steps
+    pub(crate) fn render_json(&self, scope: &mut Scope) -> Result<String, String> {
+        self.fill(scope, true)
+    }
+
+    fn fill(&self, scope: &mut Scope, json: bool) -> Result<String, String> {
+        let push_value = |out: &mut String, value: &str| match json {
+            true => out.push_str(&escape_json(value)),
+            false => out.push_str(value),
+        };
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Uuid => out.push_str(&uuid()),
+                Part::Seq => out.push_str(&scope.index.to_string()),
+                Part::RandInt(min, max) => out.push_str(&rand::random_range(*min..=*max).to_string()),
+                Part::Timestamp => {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                    out.push_str(&now.as_secs().to_string());
+                }
+                Part::Now => out.push_str(&format_timestamp(SystemTime::now())),
+                Part::Column { feeder, column } => {
+                    let (i, source) = scope
+                        .feeders
+                        .iter()
+                        .enumerate()
+                        .find(|(_, source)| source.name() == feeder)
+                        .ok_or_else(|| format!("no feeder named {}", feeder))?;
+                    let row = *scope.rows.entry(i).or_insert_with(|| source.pick(scope.worker, scope.workers));
+                    let value = source
+                        .value(row, column)
+                        .ok_or_else(|| format!("feeder {} has no column {}", feeder, column))?;
+                    push_value(&mut out, value);
+                }
+                Part::Variable(name) => {
+                    let value = scope
+                        .variables
+                        .and_then(|variables| variables.get(name))
+                        .ok_or_else(|| format!("no value for {{{{{}}}}}", name))?;
+                    push_value(&mut out, value);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            parts.push(parse_expression(&rest[start + 2..start + end])?);
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => f.write_str(text)?,
+                Part::Uuid => f.write_str("{{uuid}}")?,
+                Part::Seq => f.write_str("{{seq}}")?,
+                Part::RandInt(min, max) => write!(f, "{{{{randInt {} {}}}}}", min, max)?,
+                Part::Timestamp => f.write_str("{{timestamp}}")?,
+                Part::Now => f.write_str("{{now}}")?,
+                Part::Column { feeder, column } => write!(f, "{{{{{}.{}}}}}", feeder, column)?,
+                Part::Variable(name) => write!(f, "{{{{{}}}}}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_expression(expression: &str) -> Result<Part, String> {
+    let words: Vec<&str> = expression.split_whitespace().collect();
+    let invalid = |reason: &str| format!("Invalid template {{{{{}}}}}: {}", expression.trim(), reason);
+    let is_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    match words.as_slice() {
+        ["uuid"] => Ok(Part::Uuid),
+        ["seq"] => Ok(Part::Seq),
+        ["timestamp"] => Ok(Part::Timestamp),
+        ["now"] => Ok(Part::Now),
+        ["randInt", min, max] => match (min.parse::<i64>(), max.parse::<i64>()) {
+            (Ok(min), Ok(max)) if min <= max => Ok(Part::RandInt(min, max)),
+            (Ok(_), Ok(_)) => Err(invalid("the minimum is larger than the maximum")),
+            _ => Err(invalid("randInt takes two whole numbers, e.g. {{randInt 1 1000}}")),
+        },
+        ["randInt", ..] => Err(invalid("randInt takes two whole numbers, e.g. {{randInt 1 1000}}")),
+        [name] => match name.split_once('.') {
+            Some((feeder, column)) if is_name(feeder) && is_name(column) => Ok(Part::Column {
+                feeder: feeder.to_string(),
+                column: column.to_string(),
+            }),
+            None if is_name(name) => Ok(Part::Variable(name.to_string())),
+            _ => Err(invalid("names can only use letters, digits, _ and -")),
+        },
+        _ => Err(invalid(
+            "use uuid, seq, randInt MIN MAX, timestamp, now, FEEDER.COLUMN or a value extracted by a journey step",
+        )),
+    }
+}
+
+/// `value` escaped to go between the quotes of a JSON string
+fn escape_json(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// A random version 4 UUID
+fn uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// A request with its templates filled in, ready to send
+pub(crate) struct PreparedRequest {
+    pub method: http::Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+}
+
+/// The parts of a request that can hold templates
+#[derive(Clone)]
+pub(crate) struct RequestTemplate {
+    method: http::Method,
+    url: Template,
+    headers: Vec<(HeaderName, Template)>,
+    body: Option<Template>,
+    /// Whether a JSON Content-Type goes with the body. The test's own one is
+    /// already among the headers sent with every request.
+    json: bool,
+}
+
+impl RequestTemplate {
+    /// Fills in the templates. The URL is joined onto `base` when there is one.
+    pub fn render(&self, base: Option<&Url>, scope: &mut Scope) -> Result<PreparedRequest, String> {
+        let url = self.url.render(scope)?;
+        let url = match base {
+            Some(base) => base.join(&url).map_err(|e| format!("invalid URL {}: {}", url, e))?.to_string(),
+            None => url,
+        };
+        let mut headers = HeaderMap::new();
+        for (name, template) in &self.headers {
+            let value = template.render(scope)?;
+            let value = HeaderValue::from_str(&value).map_err(|_| format!("invalid value for header {}", name))?;
+            headers.append(name.clone(), value);
+        }
+        if self.json && self.body.is_some() {
+            set_json_content_type(&mut headers);
+        }
+        let body = self.body.as_ref().map(|body| body.render_json(scope)).transpose()?;
+        Ok(PreparedRequest {
+            method: self.method.clone(),
+            url,
+            headers,
+            body,
+        })
+    }
+
+    /// The URL filled in, for steps to be relative to
+    pub fn base(&self, scope: &mut Scope) -> Result<Url, String> {
+        let url = self.url.render(scope)?;
+        Url::parse(&url).map_err(|e| format!("invalid URL {}: {}", url, e))
+    }
+
+    fn templates(&self) -> impl Iterator<Item = &Template> {
+        std::iter::once(&self.url)
+            .chain(self.headers.iter().map(|(_, template)| template))
+            .chain(self.body.as_ref())
+    }
+
+    fn step(step: &Step) -> Result<Self, String> {
+        let at = |what: &str, e: String| format!("Step \"{}\" {}: {}", step.name, what, e);
+        let mut headers = Vec::new();
+        for (name, value) in &step.headers {
+            headers.push((name.clone(), value.parse().map_err(|e| at("header", e))?));
+        }
+        Ok(Self {
+            method: step.method.to_http(),
+            url: step.url.parse().map_err(|e| at("URL", e))?,
+            headers,
+            body: match step.payload.as_deref().filter(|_| step.method.has_body()) {
+                Some(payload) => Some(payload.parse().map_err(|e| at("payload", e))?),
+                None => None,
+            },
+            json: true,
+        })
+    }
+}
+
+/// Every template of a run, parsed once before it starts
+pub(crate) struct Templates {
+    /// The request to the URL of the test, or to each endpoint of its traffic mix
+    pub targets: Vec<RequestTemplate>,
+    pub mix: Mix,
+    pub steps: Vec<RequestTemplate>,
+    /// Headers without templates, sent unchanged with every request
+    pub static_headers: HeaderMap,
+}
+
+impl Templates {
+    /// Parses the templates of `config` and checks the feeders and columns they use
+    pub fn new(config: &LoadTestConfig) -> Result<Self, String> {
+        let endpoints = match config.endpoints.as_slice() {
+            [] => vec![Endpoint::new(config.url.clone(), 1)],
+            endpoints => endpoints.to_vec(),
+        };
+        let mut urls = Vec::new();
+        for endpoint in &endpoints {
+            let url: Template = endpoint.url.parse().map_err(|e| format!("URL: {}", e))?;
+            Url::parse(&url.skeleton()).map_err(|e| format!("Invalid URL {}: {}", endpoint.url, e))?;
+            urls.push(url);
+        }
+        if config.crawl.is_some() && urls.iter().any(|url| !url.is_static()) {
+            return Err("Following links cannot be combined with templates in the URL".to_string());
+        }
+
+        let mut static_headers = HeaderMap::new();
+        let mut headers = Vec::new();
+        for (name, value) in &config.headers {
+            match value.to_str().ok().filter(|value| value.contains("{{")) {
+                Some(value) => headers.push((name.clone(), value.parse().map_err(|e| format!("Header {}: {}", name, e))?)),
+                None => {
+                    static_headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+        let body = match config.payload.as_deref().filter(|_| config.method.has_body()) {
+            Some(payload) => Some(payload.parse().map_err(|e| format!("Payload: {}", e))?),
+            None => None,
+        };
+        let targets: Vec<RequestTemplate> = urls
+            .into_iter()
+            .map(|url| RequestTemplate {
+                method: config.method.to_http(),
+                url,
+                headers: headers.clone(),
+                body: body.clone(),
+                json: false,
+            })
+            .collect();
+        if config.steps.is_empty()
+            && let Some(name) = targets.iter().flat_map(RequestTemplate::templates).flat_map(Template::variables).next()
+        {
+            return Err(format!(
+                "{{{{{}}}}} is not a known value. Use uuid, seq, randInt MIN MAX, timestamp, now, FEEDER.COLUMN \
+                or a value extracted by a journey step",
+                name
+            ));
+        }
+        let steps = config.steps.iter().map(RequestTemplate::step).collect::<Result<Vec<_>, _>>()?;
+
+        for (i, source) in config.feeders.iter().enumerate() {
+            if config.feeders[..i].iter().any(|earlier| earlier.name() == source.name()) {
+                return Err(format!("Feeder names must be unique, \"{}\" is used twice", source.name()));
+            }
+        }
+        let workers = config.profile.pool_size();
+        for (feeder, column) in targets.iter().chain(&steps).flat_map(RequestTemplate::templates).flat_map(Template::columns) {
+            let source = config
+                .feeders
+                .iter()
+                .find(|source| source.name() == feeder)
+                .ok_or_else(|| format!("{{{{{}.{}}}}} uses a feeder named {}, but there is none", feeder, column, feeder))?;
+            if !source.columns().iter().any(|name| name == column) {
+                return Err(format!(
+                    "{{{{{}.{}}}}}: feeder {} has no column {}. Its columns are {}",
+                    feeder,
+                    column,
+                    feeder,
+                    column,
+                    source.columns().join(", ")
+                ));
+            }
+        }
+        for source in &config.feeders {
+            if source.strategy() == Strategy::Unique && source.len() < workers as usize {
+                return Err(format!(
+                    "Feeder {} has {} rows, but the unique strategy needs one for each of the {} workers",
+                    source.name(),
+                    source.len(),
+                    workers
+                ));
+            }
+        }
+        Ok(Self {
+            targets,
+            mix: Mix::new(&endpoints),
+            steps,
+            static_headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Method;
+    use crate::journey::Step;
+
+    fn render(template: &str, scope: &mut Scope) -> String {
+        template.parse::<Template>().unwrap().render(scope).unwrap()
+    }
+
+    #[test]
+    fn templates_are_parsed() {
+        let template: Template = "/users/{{ csv.user_id }}?n={{randInt 1 10}}&{{token}}&{{uuid}}{{".parse().unwrap();
+        assert!(!template.is_static());
+        assert_eq!(template.columns().collect::<Vec<_>>(), vec![("csv", "user_id")]);
+        assert_eq!(template.variables().collect::<Vec<_>>(), vec!["token"]);
+        assert_eq!(template.skeleton(), "/users/0?n=0&0&0{{");
+        assert_eq!(template.to_string(), "/users/{{csv.user_id}}?n={{randInt 1 10}}&{{token}}&{{uuid}}{{");
+        assert!("plain {text}".parse::<Template>().unwrap().is_static());
+
+        assert!("{{randInt 10 1}}".parse::<Template>().is_err());
+        assert!("{{randInt 1}}".parse::<Template>().is_err());
+        assert!("{{upper name}}".parse::<Template>().is_err());
+        assert!("{{}}".parse::<Template>().is_err());
+        assert!("{{a.b.c}}".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn values_are_filled_in_per_request() {
+        let feeders = vec![Arc::new(Feeder::parse_csv("csv", "id,name\n7,Ann\n8,Bo\n", Strategy::Sequential).unwrap())];
+        let variables = HashMap::from([("token".to_string(), "abc".to_string())]);
+        let mut rows = HashMap::new();
+        let mut scope = Scope {
+            index: 41,
+            worker: 0,
+            workers: 1,
+            feeders: &feeders,
+            variables: Some(&variables),
+            rows: &mut rows,
+        };
+        // Columns used together come from the same row
+        assert_eq!(render("{{seq}} {{csv.id}} {{csv.name}} {{token}}", &mut scope), "41 7 Ann abc");
+        scope.rows.clear();
+        assert_eq!(render("{{csv.id}}", &mut scope), "8");
+
+        let uuid = render("{{uuid}}", &mut scope);
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        let number: i64 = render("{{randInt -5 5}}", &mut scope).parse().unwrap();
+        assert!((-5..=5).contains(&number));
+        assert!(render("{{timestamp}}", &mut scope).parse::<u64>().unwrap() > 1_600_000_000);
+        assert!(render("{{now}}", &mut scope).ends_with('Z'));
+        assert!("{{missing}}".parse::<Template>().unwrap().render(&mut scope).is_err());
+    }
+
+    #[test]
+    fn values_are_escaped_in_json_payloads() {
+        let feeders = vec![Arc::new(Feeder::parse_csv("csv", "name\n\"Say \"\"hi\"\"\\\"\n", Strategy::Sequential).unwrap())];
+        let variables = HashMap::from([("token".to_string(), "a\nb".to_string())]);
+        let mut rows = HashMap::new();
+        let mut scope = Scope {
+            index: 0,
+            worker: 0,
+            workers: 1,
+            feeders: &feeders,
+            variables: Some(&variables),
+            rows: &mut rows,
+        };
+        let template: Template = r#"{"name": "{{csv.name}}", "token": "{{token}}"}"#.parse().unwrap();
+        let body: serde_json::Value = serde_json::from_str(&template.render_json(&mut scope).unwrap()).unwrap();
+        assert_eq!(body["name"], r#"Say "hi"\"#);
+        assert_eq!(body["token"], "a\nb");
+        assert_eq!(render("{{csv.name}}", &mut scope), r#"Say "hi"\"#);
+    }
+
+    #[test]
+    fn steps_are_rendered_relative_to_the_target() {
+        let step = Step::new("order", "/users/{{id}}/orders")
+            .method(Method::Post)
+            .header(http::header::AUTHORIZATION, "Bearer {{token}}")
+            .payload(r#"{"id": "{{uuid}}"}"#);
+        let request = RequestTemplate::step(&step).unwrap();
+        let variables = HashMap::from([("id".to_string(), "42".to_string()), ("token".to_string(), "abc".to_string())]);
+        let mut rows = HashMap::new();
+        let mut scope = Scope {
+            index: 0,
+            worker: 0,
+            workers: 1,
+            feeders: &[],
+            variables: Some(&variables),
+            rows: &mut rows,
+        };
+        let base = Url::parse("http://localhost:8080/app/").unwrap();
+        let request = request.render(Some(&base), &mut scope).unwrap();
+        assert_eq!(request.url, "http://localhost:8080/users/42/orders");
+        assert_eq!(request.headers[http::header::AUTHORIZATION], "Bearer abc");
+        assert_eq!(request.headers[http::header::CONTENT_TYPE], "application/json");
+        assert!(request.body.unwrap().starts_with(r#"{"id": ""#));
+    }
+
+    #[test]
+    fn configs_are_checked_before_the_run() {
+        let feeder = || Feeder::parse_csv("csv", "id\n1\n2\n", Strategy::Unique).unwrap();
+        let check = |config: LoadTestConfig| Templates::new(&config).map(|_| ());
+        let config = LoadTestConfig::new("http://localhost/users/{{csv.id}}").workers(2).feeder(feeder());
+        assert!(check(config.clone()).is_ok());
+        assert!(check(config.clone().workers(3)).unwrap_err().contains("unique strategy"));
+        assert!(check(LoadTestConfig::new("http://localhost/{{csv.id}}")).unwrap_err().contains("there is none"));
+        assert!(check(LoadTestConfig::new("http://localhost/{{csv.name}}").workers(1).feeder(feeder())).unwrap_err().contains("no column name"));
+        assert!(check(LoadTestConfig::new("http://localhost/{{token}}")).unwrap_err().contains("not a known value"));
+        assert!(check(LoadTestConfig::new("{{uuid}}")).unwrap_err().starts_with("Invalid URL"));
+    }
+}
@@ -0,0 +1,230 @@
+use crate::profile::parse_duration;
+use crate::stats::Stats;
+use std::fmt;
+use std::str::FromStr;
+
+/// A pass/fail condition on the results of a run, parsed from an expression
+/// such as `p95<300ms`, `mean<=1s`, `error_rate<1%` or `throughput>=100`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    expression: String,
+    metric: Metric,
+    comparison: Comparison,
+    /// Milliseconds for latencies, percent for the error rate
+    limit: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    Percentile(f64),
+    Mean,
+    Min,
+    Max,
+    ErrorRate,
+    Failures,
+    Throughput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// The outcome of checking one threshold
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdResult {
+    pub threshold: Threshold,
+    /// The measured value, formatted with its unit. None when there was nothing
+    /// to measure, e.g. a latency threshold when every request failed.
+    pub actual: Option<String>,
+    pub passed: bool,
+}
+
+impl Metric {
+    fn is_latency(&self) -> bool {
+        matches!(self, Metric::Percentile(_) | Metric::Mean | Metric::Min | Metric::Max)
+    }
+
+    fn measure(&self, stats: &Stats) -> Option<f64> {
+        let millis = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+        if self.is_latency() && stats.successes() == 0 {
+            return None;
+        }
+        match *self {
+            Metric::Percentile(percentile) => Some(millis(stats.percentile(percentile))),
+            Metric::Mean => Some(millis(stats.mean())),
+            Metric::Min => Some(millis(stats.min())),
+            Metric::Max => Some(millis(stats.max())),
+            Metric::ErrorRate if stats.total() == 0 => None,
+            Metric::ErrorRate => Some(stats.fails() as f64 * 100.0 / stats.total() as f64),
+            Metric::Failures => Some(stats.fails() as f64),
+            Metric::Throughput => Some(stats.throughput()),
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self {
+            _ if self.is_latency() => format!("{:.1}ms", value),
+            Metric::ErrorRate => format!("{:.2}%", value),
+            Metric::Failures => format!("{}", value),
+            _ => format!("{:.1}/s", value),
+        }
+    }
+}
+
+impl Comparison {
+    fn holds(&self, actual: f64, limit: f64) -> bool {
+        match self {
+            Comparison::Less => actual < limit,
+            Comparison::LessOrEqual => actual <= limit,
+            Comparison::Greater => actual > limit,
+            Comparison::GreaterOrEqual => actual >= limit,
+        }
+    }
+}
+
+impl Threshold {
+    pub fn check(&self, stats: &Stats) -> ThresholdResult {
+        let actual = self.metric.measure(stats);
+        ThresholdResult {
+            threshold: self.clone(),
+            passed: actual.is_some_and(|actual| self.comparison.holds(actual, self.limit)),
+            actual: actual.map(|actual| self.metric.format(actual)),
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression: String = s.split_whitespace().collect();
+        let invalid = |reason: &str| format!("Invalid threshold \"{}\": {}", s.trim(), reason);
+
+        let at = expression
+            .find(['<', '>'])
+            .ok_or_else(|| invalid("use a comparison like p95<300ms"))?;
+        let (name, rest) = expression.split_at(at);
+        let (comparison, value) = match rest.as_bytes() {
+            [b'<', b'=', ..] => (Comparison::LessOrEqual, &rest[2..]),
+            [b'>', b'=', ..] => (Comparison::GreaterOrEqual, &rest[2..]),
+            [b'<', ..] => (Comparison::Less, &rest[1..]),
+            _ => (Comparison::Greater, &rest[1..]),
+        };
+
+        let metric = match name.to_ascii_lowercase().as_str() {
+            "mean" | "avg" | "average" => Metric::Mean,
+            "min" => Metric::Min,
+            "max" => Metric::Max,
+            "error_rate" => Metric::ErrorRate,
+            "failures" | "errors" => Metric::Failures,
+            "throughput" | "rps" => Metric::Throughput,
+            name => match name.strip_prefix('p').map(str::parse::<f64>) {
+                Some(Ok(percentile)) if (0.0..=100.0).contains(&percentile) => Metric::Percentile(percentile),
+                _ => {
+                    return Err(invalid(
+                        "unknown metric. Use pNN, mean, min, max, error_rate, failures or throughput",
+                    ));
+                }
+            },
+        };
+
+        let limit = if metric.is_latency() {
+            if value.parse::<f64>().is_ok() {
+                return Err(invalid("latency limits need a unit, e.g. 300ms or 1.5s"));
+            }
+            parse_duration(value).map_err(|e| invalid(&e))?.as_secs_f64() * 1000.0
+        } else {
+            let number = match metric {
+                Metric::ErrorRate => value.strip_suffix('%').unwrap_or(value),
+                Metric::Throughput => value.strip_suffix("/s").unwrap_or(value),
+                _ => value,
+            };
+            number.parse::<f64>().map_err(|_| invalid("the limit is not a number"))?
+        };
+
+        Ok(Self {
+            expression,
+            metric,
+            comparison,
+            limit,
+        })
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl fmt::Display for ThresholdResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.passed { "passed" } else { "failed" };
+        match &self.actual {
+            Some(actual) => write!(f, "{} {} (actual {})", self.threshold, verdict, actual),
+            None => write!(f, "{} {} (nothing to measure)", self.threshold, verdict),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn stats(latencies_ms: &[u64], failures: u64) -> Stats {
+        let mut stats = Stats::new();
+        for &latency in latencies_ms {
+            stats.record(Duration::from_millis(latency));
+        }
+        for _ in 0..failures {
+            stats.record_failure();
+        }
+        stats
+    }
+
+    #[test]
+    fn expressions_are_parsed() {
+        let threshold: Threshold = "p95 < 300ms".parse().unwrap();
+        assert_eq!(threshold.metric, Metric::Percentile(95.0));
+        assert_eq!(threshold.comparison, Comparison::Less);
+        assert_eq!(threshold.limit, 300.0);
+        assert_eq!(threshold.to_string(), "p95<300ms");
+
+        let threshold: Threshold = "error_rate<=1%".parse().unwrap();
+        assert_eq!((threshold.metric, threshold.comparison, threshold.limit), (Metric::ErrorRate, Comparison::LessOrEqual, 1.0));
+        let threshold: Threshold = "p99.9<1.5s".parse().unwrap();
+        assert_eq!((threshold.metric, threshold.limit), (Metric::Percentile(99.9), 1500.0));
+        assert_eq!("throughput>=100/s".parse::<Threshold>().unwrap().limit, 100.0);
+
+        assert!("p95<300".parse::<Threshold>().is_err());
+        assert!("p95=300ms".parse::<Threshold>().is_err());
+        assert!("latency<300ms".parse::<Threshold>().is_err());
+        assert!("error_rate<lots".parse::<Threshold>().is_err());
+    }
+
+    #[test]
+    fn thresholds_are_checked_against_stats() {
+        let stats = stats(&[100, 200, 400], 1);
+        let check = |expression: &str| expression.parse::<Threshold>().unwrap().check(&stats);
+        assert!(check("max<=500ms").passed);
+        assert!(!check("max<300ms").passed);
+        assert!(check("mean>=200ms").passed);
+        assert!(check("error_rate<30%").passed);
+        let result = check("error_rate<10%");
+        assert!(!result.passed);
+        assert_eq!(result.actual.as_deref(), Some("25.00%"));
+        assert!(check("failures<2").passed);
+    }
+
+    #[test]
+    fn latency_thresholds_fail_without_successes() {
+        let result = "p95<300ms".parse::<Threshold>().unwrap().check(&stats(&[], 3));
+        assert!(!result.passed);
+        assert_eq!(result.actual, None);
+    }
+}